@@ -4,20 +4,29 @@ use std::collections::HashMap;
 pub use dragger::*;
 use num_cpus;
 use rayon::prelude::*;
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::SystemTime;
+#[path = "stats.rs"] mod stats;
+pub use stats::*;
+#[path = "transposition.rs"] mod transposition;
+pub use transposition::*;
+#[path = "searcher.rs"] mod searcher;
+pub use searcher::*;
 
 
 pub struct Algorithms {
     pub perspective: Team,
     pub opponent: Team,
 
-    // visited_states: Vec<>,
-    // hash_table: HashMap<u64>,
-
-    pub evaluated_states: isize,
-    pub pruned_states: isize,
-    pub transpositions: isize,
-    pub nth_move: isize
+    pub counters: SearchCounters,
+    pub tt: Arc<TranspositionTable>,
+    pub stop: Arc<AtomicBool>,
+    pub stats: SearchStats,
+    pub multi_pv: usize,
+    pub lines: Vec<PvLine>,
+    pub nth_move: isize,
+    pub eval: Arc<EvalParams>
 }
 
 impl Algorithms {
@@ -26,14 +35,42 @@ impl Algorithms {
             perspective: color,
             opponent: color.other(),
 
-            // visited_states: Vec::new(),
-            // hash_table: HashMap::new(),
+            counters: SearchCounters::new(),
+            tt: Arc::new(TranspositionTable::new()),
+            stop: Arc::new(AtomicBool::new(false)),
+            stats: SearchStats::default(),
+            multi_pv: 1,
+            lines: Vec::new(),
+            nth_move: 0,
+            eval: Arc::new(EvalParams::active().clone())
+        }
+    }
+    pub fn fork(&self) -> Self {
+        // shares the transposition table, but has its own counters and stop flag
+        Self {
+            perspective: self.perspective,
+            opponent: self.opponent,
 
-            evaluated_states: 0,
-            pruned_states: 0,
-            transpositions: 0,
-            nth_move: 0
+            counters: SearchCounters::new(),
+            tt: self.tt.clone(),
+            stop: Arc::new(AtomicBool::new(false)),
+            stats: SearchStats::default(),
+            multi_pv: self.multi_pv,
+            lines: Vec::new(),
+            nth_move: self.nth_move,
+            eval: self.eval.clone()
+        }
+    }
+    pub fn stopped(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+    pub fn set_perspective(&mut self, color: Team) {
+        if color != self.perspective {
+            // evaluations are stored relative to the old perspective
+            self.tt.clear();
         }
+        self.perspective = color;
+        self.opponent = color.other();
     }
     pub fn get_valid_moves(&self, state: &mut Board, color: Team) -> Vec<(Piece, Move)> {
         let mut valid_moves = Vec::new();
@@ -48,92 +85,233 @@ impl Algorithms {
         return valid_moves;
     }
     pub fn get_ordered_valid_moves(&self, mut state: &mut Board, color: Team) -> Vec<(Piece, Move)> {
-        let mut valid_moves = self.get_valid_moves(&mut state, color);
-        let mut scores = Vec::new();
-        for m in valid_moves.iter() {
-            let mut move_score_guess = 0;
-            let capture_piece = state.tiles[m.1.end.row as usize][m.1.end.col as usize].present_piece;
-            if capture_piece.is_some() {
-                move_score_guess += 10 * capture_piece.unwrap().value_mg - m.0.value_mg;
-            }
+        let valid_moves = self.get_valid_moves(&mut state, color);
+        let mut scored: Vec<(i32, (Piece, Move))> = valid_moves.into_iter().map(|m| {
+            let mut move_score_guess = state.see(&m.1);
             if (m.0.ptype == PieceType::Pawn) && ((m.1.end.row == 0) || (m.1.end.row == 7)) {
                 move_score_guess += PieceType::Queen.value_mg();
             }
-            'outer: for p in state.valid_moves.keys() {
-                if p.color != color {
-                    for action in state.valid_moves.get(p).unwrap().iter() {
-                        if m.1.end == action.end {
-                            move_score_guess -= (m.0.value_mg - p.value_mg).max(100);
-                            break 'outer;
-                        }
-                    }
+            (move_score_guess, m)
+        }).collect();
+        scored.sort_by_key(|(score, _)| -score);
+        return scored.into_iter().map(|(_, m)| m).collect();
+    }
+    pub fn get_ordered_captures(&self, mut state: &mut Board, color: Team) -> Vec<(Piece, Move)> {
+        let valid_moves = self.get_valid_moves(&mut state, color);
+        let mut scored: Vec<(i32, (Piece, Move))> = Vec::new();
+        for m in valid_moves.into_iter() {
+            let is_capture = state.tiles[m.1.end.row as usize][m.1.end.col as usize].has_piece() || (
+                (m.0.ptype == PieceType::Pawn) && (m.1.initial.col != m.1.end.col));
+            if is_capture {
+                // losing captures are pruned
+                let see = state.see(&m.1);
+                if see >= 0 {
+                    scored.push((see, m));
                 }
             }
-            scores.push(move_score_guess);
         }
-        let valid_moves_cpy = valid_moves.clone();
-        valid_moves.sort_by_key(|m| scores[valid_moves_cpy.iter().position(|i| i == m).unwrap()]);
-        return valid_moves;
+        scored.sort_by_key(|(score, _)| -score);
+        return scored.into_iter().map(|(_, m)| m).collect();
     }
 
 
-    pub fn alphabeta(&self, mut state: Board, depth: isize, perspective: f32, mut alpha: f32, beta: f32) -> (f32, (Option<Piece>, Option<Move>)) {
+    pub fn alphabeta(&self, mut state: Board, depth: isize, ply: usize, perspective: f32, mut alpha: f32, mut beta: f32) -> (f32, (Option<Piece>, Option<Move>)) {
+        if self.stopped() {
+            return (0.0, (None, None));
+        }
+        self.counters.add_node(ply);
         state.calc_team_valid_moves(Team::White);
         state.calc_team_valid_moves(Team::Black);
-        if (depth == 0) || state.is_terminal() {
-            // self.evaluated_states += 1;
-            return (perspective * state.evaluate(self.perspective), (None, None));
+        if state.is_terminal() {
+            self.counters.add_evaluated();
+            return (perspective * state.evaluate_with(&self.eval, self.perspective), (None, None));
+        }
+        if depth == 0 {
+            return (self.quiescence(state, ply, perspective, alpha, beta), (None, None));
         }
 
         let color = if perspective == 1.0 { self.perspective } else { self.opponent };
+        let key = TranspositionTable::key(&state, color);
+        let entry = self.tt.probe(key);
+        self.counters.add_tt_probe(entry.is_some());
+        if let Some(entry) = entry {
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return (entry.eval, entry.best_move),
+                    Bound::Lower => alpha = alpha.max(entry.eval),
+                    Bound::Upper => beta = beta.min(entry.eval)
+                }
+                if alpha >= beta {
+                    return (entry.eval, entry.best_move);
+                }
+            }
+        }
+
+        let alpha_orig = alpha;
         let mut best_eval = f32::MIN;
         let mut best_move = (None, None);
-        for m in self.get_ordered_valid_moves(&mut state, color) {
+        let mut searched = 0;
+        for (i, m) in self.get_ordered_valid_moves(&mut state, color).iter().enumerate() {
             let mut temp_board = state.copy();
             temp_board.execute_move(&mut m.0.copy(), m.1.copy(), false, false);
-            let evaluation = -self.alphabeta(temp_board, depth - 1, -perspective, -beta, -alpha).0;
+            let evaluation = -self.alphabeta(temp_board, depth - 1, ply + 1, -perspective, -beta, -alpha).0;
+            searched += 1;
             best_eval = best_eval.max(evaluation);
             if best_eval == evaluation {
                 best_move = (Some(m.0), Some(m.1.clone()));
             }
             alpha = alpha.max(best_eval);
             if alpha >= beta {
-                // self.pruned_states += 1;
+                self.counters.add_cutoff(i);
                 break;
             }
         }
-        // self.evaluated_states += 1;
+        self.counters.add_expanded(searched);
+        if self.stopped() {
+            return (best_eval, best_move);
+        }
+
+        let bound = if best_eval <= alpha_orig {
+            Bound::Upper
+        }
+        else if best_eval >= beta {
+            Bound::Lower
+        }
+        else {
+            Bound::Exact
+        };
+        self.tt.store(key, TTEntry { depth: depth, eval: best_eval, bound: bound, best_move: best_move.clone() });
         return (best_eval, best_move);
     }
-    pub fn search_multi_worker(&self, children: std::iter::StepBy<std::slice::Iter<'_, (Piece, Move)>>, state: Board, depth: isize, perspective: f32, mut alpha: f32, beta: f32) -> (f32, (Option<Piece>, Option<Move>)) {
-        let mut best_eval = f32::MIN;
-        let mut best_move = (None, None);
-        for m in children {
+    pub fn quiescence(&self, mut state: Board, ply: usize, perspective: f32, mut alpha: f32, beta: f32) -> f32 {
+        if self.stopped() {
+            return 0.0;
+        }
+        self.counters.add_node(ply);
+        self.counters.add_evaluated();
+        let stand_pat = perspective * state.evaluate_with(&self.eval, self.perspective);
+        if stand_pat >= beta {
+            return stand_pat;
+        }
+        alpha = alpha.max(stand_pat);
+
+        let color = if perspective == 1.0 { self.perspective } else { self.opponent };
+        let mut best_eval = stand_pat;
+        for (i, m) in self.get_ordered_captures(&mut state, color).iter().enumerate() {
             let mut temp_board = state.copy();
             temp_board.execute_move(&mut m.0.copy(), m.1.copy(), false, false);
-            let evaluation = -self.alphabeta(temp_board, depth - 1, -perspective, -beta, -alpha).0;
+            let evaluation = -self.quiescence(temp_board, ply + 1, -perspective, -beta, -alpha);
             best_eval = best_eval.max(evaluation);
-            if best_eval == evaluation {
-                best_move = (Some(m.0), Some(m.1.clone()));
-            }
             alpha = alpha.max(best_eval);
             if alpha >= beta {
-                // self.pruned_states += 1;
+                self.counters.add_cutoff(i);
                 break;
             }
         }
-        return (best_eval, best_move);
+        return best_eval;
+    }
+    pub fn search_multi_worker(&self, children: std::iter::StepBy<std::slice::Iter<'_, (Piece, Move)>>, state: Board, depth: isize, perspective: f32, mut alpha: f32, beta: f32) -> Vec<(f32, (Piece, Move))> {
+        let mut results = Vec::new();
+        for (i, m) in children.enumerate() {
+            let mut temp_board = state.copy();
+            temp_board.execute_move(&mut m.0.copy(), m.1.copy(), false, false);
+            let evaluation = -self.alphabeta(temp_board, depth - 1, 1, -perspective, -beta, -alpha).0;
+            results.push((evaluation, (m.0, m.1.clone())));
+            if self.multi_pv > 1 {
+                // every root move needs an exact score, so the window is never narrowed
+                continue;
+            }
+            alpha = alpha.max(evaluation);
+            if alpha >= beta {
+                self.counters.add_cutoff(i);
+                break;
+            }
+        }
+        return results;
     }
-    pub fn search_multi(&mut self, mut state: Board, depth: isize) -> (f32, (Option<Piece>, Option<Move>)) {
+    pub fn search_multi(&mut self, state: Board, depth: isize) -> SearchResult {
+        self.counters.reset();
+        self.tt.new_search();
+        let start_time = SystemTime::now();
+        let root_moves = self.search_root(state.copy(), depth);
+        self.stats = self.counters.snapshot(depth, SystemTime::now().duration_since(start_time).unwrap().as_millis());
+        self.lines = self.collect_lines(&state, &root_moves, depth);
+        return best_of(&root_moves);
+    }
+    pub fn search(&mut self, state: Board, max_depth: isize, progress: &Mutex<SearchProgress>) -> SearchResult {
+        self.counters.reset();
+        self.tt.new_search();
+        let start_time = SystemTime::now();
+        let mut best = (f32::MIN, (None, None));
+        // search_root looks at least two plies ahead
+        for depth in 2..=max_depth {
+            let root_moves = self.search_root(state.copy(), depth);
+            if self.stopped() {
+                break;
+            }
+            self.stats = self.counters.snapshot(depth, SystemTime::now().duration_since(start_time).unwrap().as_millis());
+            self.lines = self.collect_lines(&state, &root_moves, depth);
+            best = best_of(&root_moves);
+            *progress.lock().unwrap() = SearchProgress {
+                depth: depth,
+                evaluation: best.0,
+                best_move: best.1.1.clone(),
+                lines: self.lines.clone()
+            };
+        }
+        return best;
+    }
+    fn search_root(&self, mut state: Board, depth: isize) -> Vec<(f32, (Piece, Move))> {
+        self.counters.add_node(0);
         let child_nodes = self.get_ordered_valid_moves(&mut state, self.perspective);
+        self.counters.add_expanded(child_nodes.len());
         let n_threads = num_cpus::get();
         let results = Mutex::new(Vec::new());
         (0..(n_threads.min(child_nodes.len()))).into_par_iter().for_each(|i| {
             let result = self.search_multi_worker(child_nodes[i.min(child_nodes.len())..child_nodes.len()].iter().step_by(n_threads), state.copy(), depth - 1, 1.0, f32::MIN, f32::MAX);
-            results.lock().unwrap().push(result);
+            results.lock().unwrap().extend(result);
         });
-        let res = results.lock().unwrap().clone();
-        let best = res.iter().max_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal)).unwrap();
-        return best.clone();
+        let mut res = results.into_inner().unwrap();
+        res.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
+        return res;
+    }
+    fn collect_lines(&self, state: &Board, root_moves: &Vec<(f32, (Piece, Move))>, depth: isize) -> Vec<PvLine> {
+        root_moves.iter().take(self.multi_pv.max(1)).map(|(evaluation, m)| {
+            PvLine {
+                evaluation: *evaluation,
+                moves: self.principal_variation(state, m.clone(), depth)
+            }
+        }).collect()
+    }
+    pub fn principal_variation(&self, state: &Board, best: (Piece, Move), depth: isize) -> Vec<(Piece, Move)> {
+        let mut board = state.copy();
+        let mut color = best.0.color;
+        let mut next = Some(best);
+        let mut pv = Vec::new();
+        while let Some((_, action)) = next {
+            if (pv.len() as isize >= depth) || !board.tiles[action.initial.row as usize][action.initial.col as usize].has_team(color) {
+                break;
+            }
+            let mut piece = *board.tiles[action.initial.row as usize][action.initial.col as usize].piece();
+            board.calc_team_valid_moves(color);
+            board.execute_move(&mut piece, action.copy(), false, false);
+            board.set_en_passant(&mut piece, action.copy());
+            pv.push((piece, action));
+
+            color = color.other();
+            next = match self.tt.probe(TranspositionTable::key(&board, color)) {
+                Some(TTEntry { best_move: (Some(piece), Some(action)), .. }) => Some((piece, action)),
+                _ => None
+            };
+        }
+        return pv;
+    }
+}
+
+
+fn best_of(root_moves: &Vec<(f32, (Piece, Move))>) -> SearchResult {
+    match root_moves.first() {
+        Some((evaluation, (piece, action))) => (*evaluation, (Some(*piece), Some(action.clone()))),
+        None => (f32::MIN, (None, None))
     }
 }
\ No newline at end of file
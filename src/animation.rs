@@ -0,0 +1,69 @@
+use super::{Board, Piece, Timer, ROWS, COLS};
+
+
+pub struct Animation {
+    pub moves: Vec<(Piece, (isize, isize), (isize, isize))>,
+    pub captures: Vec<(Piece, (isize, isize))>,
+    pub appearing: Vec<(Piece, (isize, isize))>,
+    pub timer: Timer
+}
+
+impl Animation {
+    pub fn between(before: &[(Piece, (isize, isize))], after: &Board, skip: Option<isize>, duration: u128) -> Option<Self> {
+        // works on any two positions, so castling rooks, en passant and history jumps need no special cases
+        let after = piece_positions(after);
+        let mut moves = Vec::new();
+        let mut captures = Vec::new();
+        let mut appearing = Vec::new();
+        for (piece, to) in after.iter() {
+            match before.iter().find(|(other, _)| other.uid == piece.uid) {
+                Some((_, from)) if (from != to) && (skip != Some(piece.uid)) => moves.push((*piece, *from, *to)),
+                Some(_) => (),
+                None => appearing.push((*piece, *to))
+            }
+        }
+        for (piece, from) in before.iter() {
+            if after.iter().any(|(other, _)| other.uid == piece.uid) {
+                continue;
+            }
+            // a promoted pawn slides onto the square where its new piece appears
+            match appearing.iter().find(|(other, _)| other.color == piece.color) {
+                Some((_, to)) if skip != Some(piece.uid) => moves.push((*piece, *from, *to)),
+                Some(_) => (),
+                None => captures.push((*piece, *from))
+            }
+        }
+        if moves.is_empty() && captures.is_empty() && appearing.is_empty() {
+            return None;
+        }
+        let mut timer = Timer::new(duration);
+        timer.activate();
+        return Some(Self {
+            moves: moves,
+            captures: captures,
+            appearing: appearing,
+            timer: timer
+        });
+    }
+    pub fn progress(&self) -> f32 {
+        // ease out so pieces settle into their squares
+        let t = self.timer.percent_done.clamp(0.0, 1.0);
+        1.0 - (1.0 - t).powi(3)
+    }
+    pub fn hides(&self, piece: &Piece) -> bool {
+        self.moves.iter().any(|(other, _, _)| other == piece) || self.appearing.iter().any(|(other, _)| other == piece)
+    }
+}
+
+
+pub fn piece_positions(board: &Board) -> Vec<(Piece, (isize, isize))> {
+    let mut positions = Vec::new();
+    for row in 0..ROWS {
+        for col in 0..COLS {
+            if let Some(piece) = board.tiles[row][col].present_piece {
+                positions.push((piece, (row as isize, col as isize)));
+            }
+        }
+    }
+    return positions;
+}
\ No newline at end of file
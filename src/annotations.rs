@@ -0,0 +1,133 @@
+use macroquad::prelude::*;
+use super::{square_name, parse_square};
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MarkColor {
+    Green,
+    Red,
+    Blue,
+    Yellow
+}
+
+impl MarkColor {
+    pub fn from_modifiers(shift: bool, alt: bool) -> Self {
+        match (shift, alt) {
+            (true, true) => Self::Yellow,
+            (true, false) => Self::Red,
+            (false, true) => Self::Blue,
+            (false, false) => Self::Green
+        }
+    }
+    pub fn as_char(&self) -> char {
+        match *self {
+            Self::Green => 'G',
+            Self::Red => 'R',
+            Self::Blue => 'B',
+            Self::Yellow => 'Y'
+        }
+    }
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'G' => Some(Self::Green),
+            'R' => Some(Self::Red),
+            'B' => Some(Self::Blue),
+            'Y' => Some(Self::Yellow),
+            _ => None
+        }
+    }
+    pub fn color(&self) -> Color {
+        match *self {
+            Self::Green => Color::from_rgba(21, 120, 27, 170),
+            Self::Red => Color::from_rgba(136, 32, 32, 170),
+            Self::Blue => Color::from_rgba(0, 48, 136, 170),
+            Self::Yellow => Color::from_rgba(230, 143, 0, 170)
+        }
+    }
+}
+
+
+#[derive(Clone, Default, PartialEq)]
+pub struct Annotations {
+    pub arrows: Vec<((isize, isize), (isize, isize), MarkColor)>,
+    pub squares: Vec<((isize, isize), MarkColor)>
+}
+
+impl Annotations {
+    pub fn toggle_arrow(&mut self, from: (isize, isize), to: (isize, isize), color: MarkColor) {
+        // drawing the same arrow again removes it, a different colour repaints it
+        match self.arrows.iter().position(|arrow| (arrow.0 == from) && (arrow.1 == to)) {
+            Some(idx) if self.arrows[idx].2 == color => { self.arrows.remove(idx); },
+            Some(idx) => self.arrows[idx].2 = color,
+            None => self.arrows.push((from, to, color))
+        }
+    }
+    pub fn toggle_square(&mut self, square: (isize, isize), color: MarkColor) {
+        match self.squares.iter().position(|mark| mark.0 == square) {
+            Some(idx) if self.squares[idx].1 == color => { self.squares.remove(idx); },
+            Some(idx) => self.squares[idx].1 = color,
+            None => self.squares.push((square, color))
+        }
+    }
+    pub fn to_comment(&self) -> String {
+        let mut commands = Vec::new();
+        if !self.squares.is_empty() {
+            let marks: Vec<String> = self.squares.iter().map(|(pos, color)| format!("{}{}", color.as_char(), square_name(pos.0, pos.1))).collect();
+            commands.push(format!("[%csl {}]", marks.join(",")));
+        }
+        if !self.arrows.is_empty() {
+            let marks: Vec<String> = self.arrows.iter().map(|(from, to, color)| format!("{}{}{}", color.as_char(), square_name(from.0, from.1), square_name(to.0, to.1))).collect();
+            commands.push(format!("[%cal {}]", marks.join(",")));
+        }
+        return commands.join(" ");
+    }
+    pub fn from_comment(comment: &str) -> (Self, String) {
+        // returns the annotations along with the comment text that is left over
+        let mut annotations = Self::default();
+        let mut rest = String::new();
+        let mut text = comment;
+        while let Some(start) = text.find("[%") {
+            let end = match text[start..].find(']') {
+                Some(len) => start + len,
+                None => break
+            };
+            rest.push_str(&text[..start]);
+            let command = &text[start + 2..end];
+            let (name, args) = command.split_once(' ').unwrap_or((command, ""));
+            if (name != "csl") && (name != "cal") {
+                // other commands such as %clk and %eval stay in the text to be saved again
+                rest.push_str(&text[start..end + 1]);
+                text = &text[end + 1..];
+                continue;
+            }
+            for mark in args.split(',').map(|mark| mark.trim()) {
+                let color = match mark.chars().next().and_then(MarkColor::from_char) {
+                    Some(color) => color,
+                    None => continue
+                };
+                match (name, mark.get(1..3).and_then(parse_square), mark.get(3..5).and_then(parse_square)) {
+                    ("csl", Some(square), _) => annotations.squares.push((square, color)),
+                    ("cal", Some(from), Some(to)) => annotations.arrows.push((from, to, color)),
+                    _ => ()
+                }
+            }
+            text = &text[end + 1..];
+        }
+        rest.push_str(text);
+        return (annotations, rest.split_whitespace().collect::<Vec<&str>>().join(" "));
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_unknown_commands_in_the_comment() {
+        let (annotations, rest) = Annotations::from_comment("[%clk 0:01:02] good move [%cal Ge2e4,Rd7d5] [%eval 0.35] [%csl Ye4]");
+        assert!(annotations.arrows == [((6, 4), (4, 4), MarkColor::Green), ((1, 3), (3, 3), MarkColor::Red)]);
+        assert!(annotations.squares == [((4, 4), MarkColor::Yellow)]);
+        assert_eq!(rest, "[%clk 0:01:02] good move [%eval 0.35]");
+    }
+}
\ No newline at end of file
@@ -0,0 +1,466 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::sync::{mpsc, Arc};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use ::rand::seq::SliceRandom;
+use ::rand::thread_rng;
+use super::game::*;
+
+const PGN_DIR: &str = "assets/pgns";
+const MATCH_PGN: &str = "saves/match.pgn";
+const DEFAULT_GAMES: usize = 100;
+const OPENING_PLIES: usize = 8;
+const MAX_PLIES: usize = 400;
+// a side this far ahead in both engines' eyes for this many plies in a row wins
+const RESIGN_SCORE: f32 = 600.0;
+const RESIGN_PLIES: usize = 6;
+// a level position this late and for this long is a draw
+const DRAW_SCORE: f32 = 20.0;
+const DRAW_PLIES: usize = 20;
+const DRAW_FROM_PLY: usize = 120;
+const SPRT_ALPHA: f64 = 0.05;
+const SPRT_BETA: f64 = 0.05;
+// only used when the bundled collections cannot be read
+const DEFAULT_OPENINGS: [&str; 10] = [
+    "e4 e5 Nf3 Nc6 Bb5 a6",
+    "e4 c5 Nf3 d6 d4 cxd4",
+    "e4 e6 d4 d5 Nc3 Bb4",
+    "e4 c6 d4 d5 e5 Bf5",
+    "e4 e5 Nf3 Nf6 Nxe5 d6",
+    "d4 d5 c4 e6 Nc3 Nf6",
+    "d4 d5 c4 c6 Nf3 Nf6",
+    "d4 Nf6 c4 g6 Nc3 Bg7",
+    "c4 e5 Nc3 Nf6 g3 d5",
+    "Nf3 d5 g3 Nf6 Bg2 c6"
+];
+
+
+#[derive(Clone)]
+struct EngineConfig {
+    name: String,
+    depth: isize,
+    // milliseconds per move, zero searches every move to the full depth
+    movetime: u64,
+    eval: Arc<EvalParams>
+}
+
+impl EngineConfig {
+    fn parse(spec: &str, label: &str) -> Result<Self, String> {
+        // "depth=4,time=500,eval=params.toml,name=new"
+        let mut config = Self {
+            name: format!("{} ({})", label, spec),
+            depth: SEARCH_DEPTH,
+            movetime: 0,
+            eval: Arc::new(EvalParams::active().clone())
+        };
+        for option in spec.split(',').filter(|option| !option.is_empty()) {
+            let (key, value) = option.split_once('=').ok_or(format!("expected key=value, got \"{}\"", option))?;
+            match key {
+                "depth" => config.depth = value.parse().map_err(|_| format!("invalid depth \"{}\"", value))?,
+                "time" => config.movetime = value.parse().map_err(|_| format!("invalid time \"{}\"", value))?,
+                "eval" => config.eval = Arc::new(EvalParams::load(value)?),
+                "name" => config.name = value.to_string(),
+                _ => return Err(format!("unknown option \"{}\"", key))
+            }
+        }
+        if config.depth < 2 {
+            return Err("depth must be at least 2".to_string());
+        }
+        return Ok(config);
+    }
+    fn engine(&self, color: Team) -> Algorithms {
+        let mut engine = Algorithms::new(color);
+        engine.eval = self.eval.clone();
+        return engine;
+    }
+}
+
+
+#[derive(Clone)]
+struct Opening {
+    fen: Option<String>,
+    moves: Vec<String>
+}
+
+fn load_openings(path: &str) -> Result<Vec<Opening>, String> {
+    // either one fen per line or the first moves of every game in a pgn file
+    let text = fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+    let lines: Vec<&str> = text.lines().map(|line| line.trim()).filter(|line| !line.is_empty()).collect();
+    let openings: Vec<Opening> = if lines.iter().all(|line| Board::from_fen(line).is_ok()) {
+        lines.iter().map(|line| Opening { fen: Some(line.to_string()), moves: Vec::new() }).collect()
+    }
+    else {
+        pgn_openings(&parse_pgn(&text))
+    };
+    if openings.is_empty() {
+        return Err(format!("no openings in {}", path));
+    }
+    return Ok(openings);
+}
+
+fn pgn_openings(games: &[PgnGame]) -> Vec<Opening> {
+    let mut seen = HashSet::new();
+    let mut openings = Vec::new();
+    for pgn in games.iter().filter(|pgn| pgn.moves.len() >= OPENING_PLIES) {
+        let opening = Opening {
+            fen: pgn.tag("FEN").map(|fen| fen.to_string()),
+            moves: pgn.moves.iter().take(OPENING_PLIES).map(|pgn_move| pgn_move.san.clone()).collect()
+        };
+        if seen.insert((opening.fen.clone(), opening.moves.clone())) {
+            openings.push(opening);
+        }
+    }
+    return openings;
+}
+
+fn default_openings() -> Vec<Opening> {
+    // engines searching to a fixed depth replay the same game from the same opening, so every pair needs its own
+    let games: Vec<PgnGame> = match fs::read_dir(PGN_DIR) {
+        Ok(entries) => entries.filter_map(|entry| fs::read_to_string(entry.ok()?.path()).ok()).flat_map(|text| parse_pgn(&text)).collect(),
+        Err(_) => Vec::new()
+    };
+    let mut openings = pgn_openings(&games);
+    if openings.is_empty() {
+        return DEFAULT_OPENINGS.iter().map(|line| Opening { fen: None, moves: line.split_whitespace().map(|san| san.to_string()).collect() }).collect();
+    }
+    openings.shuffle(&mut thread_rng());
+    return openings;
+}
+
+
+struct GameRecord {
+    // from the first engine's point of view
+    score: f32,
+    reason: String,
+    pgn: PgnGame
+}
+
+fn think(engine: &mut Algorithms, board: &Board, config: &EngineConfig) -> SearchResult {
+    if config.movetime == 0 {
+        return engine.search_multi(board.copy(), config.depth);
+    }
+    // the deepest finished iteration is played once the time is up
+    let search = BackgroundSearch::start(engine, board.copy(), config.depth);
+    let deadline = Instant::now() + Duration::from_millis(config.movetime);
+    while !search.is_finished() && ((Instant::now() < deadline) || search.progress().best_move.is_none()) {
+        thread::sleep(Duration::from_millis(2));
+    }
+    if search.is_finished() {
+        return search.wait().result;
+    }
+    let progress = search.progress();
+    search.cancel();
+    let action = progress.best_move.unwrap();
+    let piece = *board.tiles[action.initial.row as usize][action.initial.col as usize].piece();
+    return (progress.evaluation, (Some(piece), Some(action)));
+}
+
+fn play_game(configs: [&EngineConfig; 2], opening: &Opening, round: usize) -> Result<GameRecord, String> {
+    // configs are in colour order, white first
+    let (mut board, mut side) = match &opening.fen {
+        Some(fen) => Board::from_fen(fen)?,
+        None => (Board::new(), Team::White)
+    };
+    let mut pgn = PgnGame::new();
+    pgn.set_tag("Event", "Engine match");
+    pgn.set_tag("Round", &round.to_string());
+    pgn.set_tag("White", &configs[0].name);
+    pgn.set_tag("Black", &configs[1].name);
+    if let Some(fen) = &opening.fen {
+        pgn.set_tag("SetUp", "1");
+        pgn.set_tag("FEN", fen);
+    }
+    let mut engines = [configs[0].engine(Team::White), configs[1].engine(Team::Black)];
+    let mut keys = Vec::new();
+    let mut halfmove_clock = 0;
+    let mut winning_plies = 0;
+    let mut level_plies = 0;
+    let mut last_score: f32 = 0.0;
+
+    let (winner, reason) = loop {
+        let ply = pgn.moves.len();
+        let idx = if side == Team::White { 0 } else { 1 };
+        if engines[idx].get_valid_moves(&mut board, side).is_empty() {
+            break match board.get_pins_and_checks(side).0 {
+                true => (side.other(), EndReason::Checkmate.as_string()),
+                false => (Team::None, EndReason::Stalemate.as_string())
+            };
+        }
+        let key = TranspositionTable::repetition_key(&board, side);
+        keys.push(key);
+        if keys.iter().filter(|other| **other == key).count() >= 3 {
+            break (Team::None, EndReason::Repetition.as_string());
+        }
+        if halfmove_clock >= 100 {
+            break (Team::None, EndReason::FiftyMoves.as_string());
+        }
+        if board.is_insufficient_material() {
+            break (Team::None, EndReason::InsufficientMaterial.as_string());
+        }
+        if ply >= MAX_PLIES {
+            break (Team::None, "Move limit".to_string());
+        }
+
+        let (piece, action, score) = if ply < opening.moves.len() {
+            match board.find_san(side, &opening.moves[ply]) {
+                Some((piece, action)) => (piece, action, 0.0),
+                None => return Err(format!("illegal opening move {}", opening.moves[ply]))
+            }
+        }
+        else {
+            let (evaluation, (piece, action)) = think(&mut engines[idx], &board, configs[idx]);
+            // centipawns from white's point of view
+            let centipawns = engines[idx].eval.to_centipawns(evaluation);
+            let score = if side == Team::White { centipawns } else { -centipawns };
+            (piece.ok_or("the engine found no move")?, action.ok_or("the engine found no move")?, score)
+        };
+
+        if ply >= opening.moves.len() {
+            winning_plies = if (score.abs() >= RESIGN_SCORE) && (score.signum() == last_score.signum()) { winning_plies + 1 } else { 0 };
+            level_plies = if (ply >= DRAW_FROM_PLY) && (score.abs() <= DRAW_SCORE) { level_plies + 1 } else { 0 };
+            last_score = score;
+            if winning_plies >= RESIGN_PLIES {
+                break (if score > 0.0 { Team::White } else { Team::Black }, "Adjudicated win".to_string());
+            }
+            if level_plies >= DRAW_PLIES {
+                break (Team::None, "Adjudicated draw".to_string());
+            }
+        }
+
+        let mut piece = piece;
+        let san = board.to_san(piece, &action);
+        let captured = board.tiles[action.end.row as usize][action.end.col as usize].has_piece();
+        halfmove_clock = if captured || (piece.ptype == PieceType::Pawn) { 0 } else { halfmove_clock + 1 };
+        board.execute_move(&mut piece, action.copy(), false, false);
+        board.set_en_passant(&mut piece, action);
+        pgn.moves.push(PgnMove {
+            san: san,
+            ..Default::default()
+        });
+        side = side.other();
+    };
+
+    pgn.result = match winner {
+        Team::White => "1-0",
+        Team::Black => "0-1",
+        Team::None => "1/2-1/2"
+    }.to_string();
+    pgn.set_tag("Result", &pgn.result.clone());
+    pgn.set_tag("Termination", &reason);
+    let score = match winner {
+        Team::White => 1.0,
+        Team::Black => 0.0,
+        Team::None => 0.5
+    };
+    return Ok(GameRecord {
+        score: score,
+        reason: reason,
+        pgn: pgn
+    });
+}
+
+
+fn score_stats(wins: usize, draws: usize, losses: usize) -> (f64, f64) {
+    // the mean score and the variance of a single game's score
+    let n = (wins + draws + losses) as f64;
+    let score = (wins as f64 + 0.5 * draws as f64) / n;
+    let variance = (wins as f64 * (1.0 - score).powi(2) + draws as f64 * (0.5 - score).powi(2) + losses as f64 * score.powi(2)) / n;
+    return (score, variance);
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    let score = score.clamp(1e-6, 1.0 - 1e-6);
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+fn score_from_elo(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_estimate(wins: usize, draws: usize, losses: usize) -> (f64, f64) {
+    // the elo difference and the half width of its 95% confidence interval
+    let (score, variance) = score_stats(wins, draws, losses);
+    let n = (wins + draws + losses) as f64;
+    let margin = 1.96 * (variance / n).sqrt();
+    let elo = elo_from_score(score);
+    return (elo, (elo_from_score(score + margin) - elo_from_score(score - margin)) / 2.0);
+}
+
+fn sprt_llr(wins: usize, draws: usize, losses: usize, elo0: f64, elo1: f64) -> f64 {
+    // normal approximation of the log likelihood ratio between the two hypotheses
+    let (score, variance) = score_stats(wins, draws, losses);
+    if variance <= 0.0 {
+        return 0.0;
+    }
+    let n = (wins + draws + losses) as f64;
+    let (s0, s1) = (score_from_elo(elo0), score_from_elo(elo1));
+    return n * (s1 - s0) * (2.0 * score - s0 - s1) / (2.0 * variance);
+}
+
+
+pub fn run(args: &[String]) {
+    // chess --match [--engine1 spec] [--engine2 spec] [--games n] [--openings path] [--concurrency n] [--pgn path] [--sprt elo0,elo1]
+    let flag_value = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|idx| args.get(idx + 1)).cloned();
+    let configs = match (
+        EngineConfig::parse(&flag_value("--engine1").unwrap_or_default(), "engine1"),
+        EngineConfig::parse(&flag_value("--engine2").unwrap_or_default(), "engine2")
+    ) {
+        (Ok(first), Ok(second)) => Arc::new([first, second]),
+        (Err(err), _) | (_, Err(err)) => {
+            eprintln!("invalid engine: {}", err);
+            return;
+        }
+    };
+    let openings = match flag_value("--openings") {
+        Some(path) => match load_openings(&path) {
+            Ok(openings) => openings,
+            Err(err) => {
+                eprintln!("{}", err);
+                return;
+            }
+        },
+        None => default_openings()
+    };
+    let openings = Arc::new(openings);
+    let games = flag_value("--games").and_then(|games| games.parse().ok()).unwrap_or(DEFAULT_GAMES);
+    if games > 2 * openings.len() {
+        println!("only {} openings for {} games, openings are reused and fixed depth engines will repeat games", openings.len(), games);
+    }
+    let concurrency = flag_value("--concurrency").and_then(|n| n.parse().ok()).unwrap_or(num_cpus::get()).max(1);
+    let pgn_path = flag_value("--pgn").unwrap_or(MATCH_PGN.to_string());
+    let (elo0, elo1) = flag_value("--sprt")
+        .and_then(|bounds| bounds.split_once(',').and_then(|(a, b)| Some((a.parse().ok()?, b.parse().ok()?))))
+        .unwrap_or((0.0, 10.0));
+    let (lower, upper) = ((SPRT_BETA / (1.0 - SPRT_ALPHA)).ln(), ((1.0 - SPRT_BETA) / SPRT_ALPHA).ln());
+
+    if let Some(dir) = Path::new(&pgn_path).parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    if let Err(err) = fs::write(&pgn_path, "") {
+        eprintln!("could not write {}: {}", pgn_path, err);
+        return;
+    }
+    println!("{} vs {}, {} games, {} at a time", configs[0].name, configs[1].name, games, concurrency);
+
+    // games come in pairs that share an opening, with the colours swapped
+    let next = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (sender, receiver) = mpsc::channel();
+    for _ in 0..concurrency.min(games) {
+        let (next, stop, sender, configs, openings) = (next.clone(), stop.clone(), sender.clone(), configs.clone(), openings.clone());
+        thread::spawn(move || {
+            while !stop.load(Ordering::Relaxed) {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                if i >= games {
+                    break;
+                }
+                let first_is_white = i % 2 == 0;
+                let colours = if first_is_white { [&configs[0], &configs[1]] } else { [&configs[1], &configs[0]] };
+                let result = play_game(colours, &openings[(i / 2) % openings.len()], i + 1).map(|mut record| {
+                    if !first_is_white {
+                        record.score = 1.0 - record.score;
+                    }
+                    record
+                });
+                if sender.send((i, result)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+    drop(sender);
+
+    let (mut wins, mut draws, mut losses) = (0, 0, 0);
+    for (i, result) in receiver {
+        let record = match result {
+            Ok(record) => record,
+            Err(err) => {
+                eprintln!("game {} aborted: {}", i + 1, err);
+                continue;
+            }
+        };
+        match record.score {
+            score if score == 1.0 => wins += 1,
+            score if score == 0.0 => losses += 1,
+            _ => draws += 1
+        }
+        if let Err(err) = fs::OpenOptions::new().append(true).open(&pgn_path).and_then(|mut file| {
+            use std::io::Write;
+            writeln!(file, "{}", record.pgn.to_text())
+        }) {
+            eprintln!("could not write {}: {}", pgn_path, err);
+        }
+        let (elo, margin) = elo_estimate(wins, draws, losses);
+        let llr = sprt_llr(wins, draws, losses, elo0, elo1);
+        println!(
+            "game {:>4}: {} - {} {} ({})  +{} ={} -{}  elo {:+.1} +/- {:.1}  llr {:.2} [{:.2}, {:.2}]",
+            i + 1, record.pgn.tag("White").unwrap_or("?"), record.pgn.tag("Black").unwrap_or("?"), record.pgn.result, record.reason,
+            wins, draws, losses, elo, margin, llr, lower, upper
+        );
+        if (llr <= lower) || (llr >= upper) {
+            println!("SPRT [{}, {}]: {} accepted", elo0, elo1, if llr >= upper { "H1, engine1 is stronger" } else { "H0, engine1 is not stronger" });
+            stop.store(true, Ordering::Relaxed);
+            break;
+        }
+    }
+
+    let played = wins + draws + losses;
+    if played > 0 {
+        let (elo, margin) = elo_estimate(wins, draws, losses);
+        println!("{} games: +{} ={} -{} for {}, elo {:+.1} +/- {:.1}", played, wins, draws, losses, configs[0].name, elo, margin);
+    }
+    println!("games written to {}", pgn_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elo_is_zero_for_an_even_score() {
+        let (elo, margin) = elo_estimate(10, 20, 10);
+        assert!(elo.abs() < 1e-9);
+        assert!(margin > 0.0);
+        let (elo, _) = elo_estimate(30, 0, 10);
+        assert!((elo - elo_from_score(0.75)).abs() < 1e-9);
+        assert!((score_from_elo(elo) - 0.75).abs() < 1e-9);
+    }
+
+    #[test]
+    fn elo_margin_shrinks_with_more_games() {
+        let (_, few) = elo_estimate(6, 8, 6);
+        let (_, many) = elo_estimate(60, 80, 60);
+        assert!(many < few);
+    }
+
+    #[test]
+    fn sprt_llr_follows_the_score() {
+        assert!(sprt_llr(60, 30, 10, 0.0, 10.0) > 0.0);
+        assert!(sprt_llr(10, 30, 60, 0.0, 10.0) < 0.0);
+        // only draws say nothing about either hypothesis
+        assert_eq!(sprt_llr(0, 10, 0, 0.0, 10.0), 0.0);
+    }
+
+    #[test]
+    fn parses_engine_specs() {
+        let config = EngineConfig::parse("depth=3,time=250,name=new", "engine1").unwrap();
+        assert_eq!(config.depth, 3);
+        assert_eq!(config.movetime, 250);
+        assert_eq!(config.name, "new");
+        assert!(EngineConfig::parse("depth=1", "engine1").is_err());
+        assert!(EngineConfig::parse("speed=3", "engine1").is_err());
+        assert!(EngineConfig::parse("depth", "engine1").is_err());
+    }
+
+    #[test]
+    fn pgn_openings_are_unique() {
+        let text = "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O 1-0\n\n1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. d3 0-1\n\n1. d4 d5 2. c4 e6 3. Nc3 Nf6 4. Bg5 Be7 *\n\n1. c4 *\n";
+        let openings = pgn_openings(&parse_pgn(text));
+        assert_eq!(openings.len(), 2);
+        assert_eq!(openings[0].moves.len(), OPENING_PLIES);
+        assert_eq!(openings[1].moves[0], "d4");
+    }
+}
@@ -10,6 +10,8 @@ pub use tile::*;
 pub use constants::*;
 #[path = "utils.rs"] mod utils;
 pub use utils::*;
+#[path = "eval.rs"] mod eval;
+pub use eval::*;
 
 
 pub struct Board {
@@ -61,20 +63,54 @@ impl Board {
             cur_uid: cur_uid
         }
     }
-    pub fn evaluate(&self, perspective: Team) -> f32 {
-        let eval = self.accumulate_material(perspective);
-        return eval;
+    pub fn evaluate_with(&self, params: &EvalParams, perspective: Team) -> f32 {
+        let eval = params.evaluate(self);
+        return if perspective == Team::White { eval } else { -eval };
     }
-    pub fn accumulate_material(&self, color: Team) -> f32 {
-        let mut v = 0.0;
+    pub fn king_square(&self, color: Team) -> Option<(isize, isize)> {
+        for (r, row) in self.tiles.iter().enumerate() {
+            for (c, tile) in row.iter().enumerate() {
+                if tile.has_team(color) && (tile.piece().ptype == PieceType::King) {
+                    return Some((r as isize, c as isize));
+                }
+            }
+        }
+        return None;
+    }
+    pub fn has_mating_material(&self, color: Team) -> bool {
+        // a lone king, or a king with a single minor piece, can never force mate
+        let mut minors = 0;
         for row in self.tiles.iter() {
             for tile in row.iter() {
                 if tile.has_team(color) {
-                    v += tile.piece().value_mg as f32;
+                    match tile.piece().ptype {
+                        PieceType::Pawn | PieceType::Rook | PieceType::Queen => return true,
+                        PieceType::Knight | PieceType::Bishop => minors += 1,
+                        _ => ()
+                    }
                 }
             };
         };
-        return v;
+        return minors > 1;
+    }
+    pub fn is_insufficient_material(&self) -> bool {
+        // neither side can mate: bare kings, a single minor piece, or bishops that all share a square colour
+        let mut minors = Vec::new();
+        for (r, row) in self.tiles.iter().enumerate() {
+            for (c, tile) in row.iter().enumerate() {
+                if tile.has_piece() {
+                    match tile.piece().ptype {
+                        PieceType::King => (),
+                        PieceType::Knight | PieceType::Bishop => minors.push((tile.piece().ptype, (r + c) % 2)),
+                        _ => return false
+                    }
+                }
+            }
+        }
+        if minors.len() <= 1 {
+            return true;
+        }
+        return minors.iter().all(|(ptype, shade)| (*ptype == PieceType::Bishop) && (*shade == minors[0].1));
     }
     pub fn is_terminal(&mut self) -> bool {
         self.in_checkmate(Team::Black) || self.in_checkmate(Team::White)
@@ -126,7 +162,7 @@ impl Board {
                 removed_pos = Some((start.row, start.col + diff));
                 self.tiles[start.row as usize][(start.col + diff) as usize].present_piece = None;
             }
-            self.check_promotion(*piece, end.copy());
+            self.check_promotion(*piece, end.copy(), action.promotion);
         }
 
         if piece.ptype == PieceType::King {
@@ -189,15 +225,65 @@ impl Board {
         };
         return true;
     }
-    pub fn check_promotion(&mut self, piece: Piece, end: Tile) {
+    pub fn check_promotion(&mut self, piece: Piece, end: Tile, ptype: PieceType) {
         if (end.row == 0) || (end.row == 7) {
-            self.tiles[end.row as usize][end.col as usize].present_piece = Some(Piece::new(PieceType::Queen, piece.color, self.cur_uid, piece.row, piece.col));
+            self.tiles[end.row as usize][end.col as usize].present_piece = Some(Piece::new(ptype, piece.color, self.cur_uid, piece.row, piece.col));
             self.cur_uid += 1;
         };
     }
     pub fn is_castling(&self, initial: Tile, end: Tile) -> bool {
         return initial.col.abs_diff(end.col) == 2;
     }
+    pub fn see(&self, action: &Move) -> i32 {
+        let mut occupancy = [[None; COLS]; ROWS];
+        for (i, row) in self.tiles.iter().enumerate() {
+            for (j, tile) in row.iter().enumerate() {
+                occupancy[i][j] = tile.present_piece;
+            }
+        }
+        let (row, col) = (action.end.row as usize, action.end.col as usize);
+        let mut attacker = match occupancy[action.initial.row as usize][action.initial.col as usize] {
+            Some(piece) => piece,
+            None => return 0
+        };
+        let mut from = (action.initial.row as usize, action.initial.col as usize);
+
+        let mut gain = Vec::from([match occupancy[row][col] {
+            Some(piece) => see_value(piece.ptype),
+            None => {
+                if (attacker.ptype == PieceType::Pawn) && (action.initial.col != action.end.col) {
+                    // en passant, the captured pawn is not on the target square
+                    occupancy[action.initial.row as usize][col] = None;
+                    see_value(PieceType::Pawn)
+                }
+                else {
+                    0
+                }
+            }
+        }]);
+        let mut side = attacker.color;
+        loop {
+            let d = gain.len();
+            gain.push(see_value(attacker.ptype) - gain[d - 1]);
+            if (-gain[d - 1]).max(gain[d]) < 0 {
+                break;
+            }
+            occupancy[from.0][from.1] = None;
+            occupancy[row][col] = Some(attacker);
+            side = side.other();
+            match least_valuable_attacker(&occupancy, row, col, side) {
+                Some(pos) => {
+                    from = pos;
+                    attacker = occupancy[pos.0][pos.1].unwrap();
+                },
+                None => break
+            }
+        }
+        for d in (1..gain.len() - 1).rev() {
+            gain[d - 1] = -(-gain[d - 1]).max(gain[d]);
+        }
+        return gain[0];
+    }
     pub fn set_en_passant(&mut self, piece: &mut Piece, action: Move) {
         for row in self.tiles.iter_mut() {
             for tile in row.iter_mut() {
@@ -303,6 +389,129 @@ impl Board {
         }
         return (in_check, pins, checks);
     }
+    pub fn find_move(&mut self, color: Team, text: &str) -> Option<(Piece, Move)> {
+        // a fifth character picks the promotion piece, a queen when it is left out
+        let promotion = match text.chars().nth(4) {
+            Some(c) => promotion_piece(c)?,
+            None => PieceType::Queen
+        };
+        let text = &text[..text.len().min(4)];
+        self.calc_team_valid_moves(color);
+        for (piece, moves) in self.valid_moves.iter() {
+            if piece.color == color {
+                for action in moves.iter() {
+                    let tile = &self.tiles[action.initial.row as usize][action.initial.col as usize];
+                    if (action.to_uci() == text) && tile.has_team(color) && (tile.piece() == piece) {
+                        return Some((*tile.piece(), action.with_promotion(promotion)));
+                    }
+                }
+            }
+        }
+        return None;
+    }
+    pub fn find_san(&mut self, color: Team, text: &str) -> Option<(Piece, Move)> {
+        let promotion = match text.split_once('=') {
+            Some((_, suffix)) => promotion_piece(suffix.chars().next()?)?,
+            None => PieceType::Queen
+        };
+        let core = san_core(text);
+        let castle = core.starts_with("O-O");
+        let ptype = match core.chars().next() {
+            Some(c) if "NBRQK".contains(c) => c,
+            _ => 'P'
+        };
+        let target = core.get(core.len().saturating_sub(2)..).unwrap_or("").to_string();
+        self.calc_team_valid_moves(color);
+        let mut candidates = Vec::new();
+        for (piece, moves) in self.valid_moves.iter() {
+            if (piece.color == color) && (castle || (piece.ptype.as_char() == ptype)) {
+                for action in moves.iter() {
+                    let tile = &self.tiles[action.initial.row as usize][action.initial.col as usize];
+                    if (castle || (square_name(action.end.row, action.end.col) == target)) && tile.has_team(color) && (tile.piece() == piece) {
+                        candidates.push((*tile.piece(), action.copy()));
+                    }
+                }
+            }
+        }
+        for (piece, action) in candidates.into_iter() {
+            if san_core(&self.to_san(piece, &action)) == core {
+                return Some((piece, action.with_promotion(promotion)));
+            }
+        }
+        return None;
+    }
+    pub fn to_san(&self, piece: Piece, action: &Move) -> String {
+        let mut board = self.copy();
+        board.calc_team_valid_moves(piece.color);
+        let end = &self.tiles[action.end.row as usize][action.end.col as usize];
+        let capture = end.has_piece() || ((piece.ptype == PieceType::Pawn) && (action.initial.col != action.end.col));
+
+        let mut san = String::new();
+        if (piece.ptype == PieceType::King) && self.is_castling(action.initial.copy(), action.end.copy()) {
+            san.push_str(if action.end.col > action.initial.col { "O-O" } else { "O-O-O" });
+        }
+        else {
+            if piece.ptype == PieceType::Pawn {
+                if capture {
+                    san.push(square_name(action.initial.row, action.initial.col).chars().next().unwrap());
+                }
+            }
+            else {
+                san.push(piece.ptype.as_char());
+                let mut ambiguous = Vec::new();
+                for (other, moves) in board.valid_moves.iter() {
+                    let tile = &board.tiles[other.row][other.col];
+                    if (other.color == piece.color) && (other.ptype == piece.ptype) && (*other != piece) && tile.has_piece() && (tile.piece() == other) {
+                        if moves.iter().any(|m| m.end == action.end) {
+                            ambiguous.push((other.row as isize, other.col as isize));
+                        }
+                    }
+                }
+                if !ambiguous.is_empty() {
+                    let name = square_name(action.initial.row, action.initial.col);
+                    if !ambiguous.iter().any(|pos| pos.1 == action.initial.col) {
+                        san.push_str(&name[..1]);
+                    }
+                    else if !ambiguous.iter().any(|pos| pos.0 == action.initial.row) {
+                        san.push_str(&name[1..]);
+                    }
+                    else {
+                        san.push_str(&name);
+                    }
+                }
+            }
+            if capture {
+                san.push('x');
+            }
+            san.push_str(&square_name(action.end.row, action.end.col));
+            if (piece.ptype == PieceType::Pawn) && ((action.end.row == 0) || (action.end.row == 7)) {
+                san.push('=');
+                san.push(action.promotion.as_char());
+            }
+        }
+
+        let mut moved = piece;
+        board.execute_move(&mut moved, action.copy(), false, false);
+        board.set_en_passant(&mut moved, action.copy());
+        let (in_check, _, _) = board.get_pins_and_checks(piece.color.other());
+        if in_check {
+            board.calc_team_valid_moves(piece.color.other());
+            san.push(if board.in_checkmate(piece.color.other()) { '#' } else { '+' });
+        }
+        return san;
+    }
+    pub fn line_to_san(&self, line: &[(Piece, Move)]) -> Vec<String> {
+        let mut board = self.copy();
+        let mut sans = Vec::new();
+        for (piece, action) in line.iter() {
+            sans.push(board.to_san(*piece, action));
+            let mut piece = *piece;
+            board.calc_team_valid_moves(piece.color);
+            board.execute_move(&mut piece, action.copy(), false, false);
+            board.set_en_passant(&mut piece, action.copy());
+        }
+        return sans;
+    }
     pub fn is_valid(&self, piece: Piece, action: Move) -> bool {
         return self.valid_moves.contains_key(&piece) && self.valid_moves.get(&piece).unwrap().contains(&action);
     }
@@ -622,10 +831,15 @@ impl Board {
                 }
             }
             else {
+                // double check, only the king can move
                 for i in 0..self.tiles.len() {
                     for j in 0..self.tiles[0].len() {
-                        if self.tiles[i][j].has_team(color) && (self.tiles[i][j].piece().ptype == PieceType::King) {
-                            self.calc_king_moves(*self.tiles[i][j].piece(), i as isize, j as isize);
+                        if self.tiles[i][j].has_team(color) {
+                            let piece = *self.tiles[i][j].piece();
+                            self.valid_moves.insert(piece, Vec::new());
+                            if piece.ptype == PieceType::King {
+                                self.calc_king_moves(piece, i as isize, j as isize);
+                            }
                         }
                     }
                 }
@@ -647,10 +861,237 @@ impl Board {
         copy.game_stage = self.game_stage;
         copy.tiles = self.tiles.clone();
         copy.zobrist_keys = self.zobrist_keys.clone();
+        copy.cur_uid = self.cur_uid;
+        copy.checkmated = self.checkmated;
+        copy.stalemate = self.stalemate;
         return copy;
     }
+    pub fn empty() -> Self {
+        let mut board = Self::new();
+        for row in board.tiles.iter_mut() {
+            for tile in row.iter_mut() {
+                tile.present_piece = None;
+            }
+        }
+        board.cur_uid = 0;
+        return board;
+    }
+    pub fn put_piece(&mut self, row: isize, col: isize, piece: Option<(Team, PieceType)>) {
+        self.tiles[row as usize][col as usize].present_piece = piece.map(|(color, ptype)| {
+            let mut piece = Piece::new(ptype, color, self.cur_uid, row as usize, col as usize);
+            // only pawns on their starting rank may still advance two squares
+            let home_row = if color == Team::White { 6 } else { 1 };
+            piece.has_moved = (ptype == PieceType::Pawn) && (row != home_row);
+            piece
+        });
+        self.cur_uid += 1;
+    }
+    pub fn castling_rights(&self) -> [bool; 4] {
+        // white king side, white queen side, black king side, black queen side
+        let unmoved = |row: usize, col: usize, ptype: PieceType, color: Team| {
+            self.tiles[row][col].has_team(color) && (self.tiles[row][col].piece().ptype == ptype) && !self.tiles[row][col].piece().has_moved
+        };
+        let mut rights = [false; 4];
+        for (i, (color, row)) in [(Team::White, 7), (Team::Black, 0)].iter().enumerate() {
+            if unmoved(*row, 4, PieceType::King, *color) {
+                rights[i * 2] = unmoved(*row, 7, PieceType::Rook, *color);
+                rights[i * 2 + 1] = unmoved(*row, 0, PieceType::Rook, *color);
+            }
+        }
+        return rights;
+    }
+    pub fn set_castling_rights(&mut self, rights: [bool; 4]) {
+        // rights are stored as the has_moved flags of the king and rooks on their home squares
+        for (i, row) in [7, 0].iter().enumerate() {
+            for (col, allowed) in [(7, rights[i * 2]), (0, rights[i * 2 + 1])].iter() {
+                if let Some(rook) = &mut self.tiles[*row][*col].present_piece {
+                    rook.has_moved = !allowed;
+                }
+            }
+            if let Some(king) = &mut self.tiles[*row][4].present_piece {
+                king.has_moved = !rights[i * 2] && !rights[i * 2 + 1];
+            }
+        }
+    }
+    pub fn en_passant_target(&self) -> Option<(isize, isize)> {
+        for (r, row) in self.tiles.iter().enumerate() {
+            for (c, tile) in row.iter().enumerate() {
+                if tile.has_piece() && (tile.piece().ptype == PieceType::Pawn) && tile.piece().en_passant {
+                    return Some((r as isize - tile.piece().dir, c as isize));
+                }
+            }
+        }
+        return None;
+    }
+    pub fn set_en_passant_file(&mut self, file: Option<isize>, next_player: Team) -> Result<(), String> {
+        for row in self.tiles.iter_mut() {
+            for tile in row.iter_mut() {
+                if let Some(piece) = &mut tile.present_piece {
+                    piece.en_passant = false;
+                }
+            }
+        }
+        let col = match file {
+            Some(col) => col,
+            None => return Ok(())
+        };
+        // the pawn that just advanced two squares belongs to the side that is not to move
+        let (row, passed, start) = if next_player == Team::White { (3, 2, 1) } else { (4, 5, 6) };
+        let skipped = self.tiles[passed][col as usize].is_empty() && self.tiles[start][col as usize].is_empty();
+        let capturable = skipped && self.can_capture_en_passant(col as usize, next_player);
+        match &mut self.tiles[row][col as usize].present_piece {
+            Some(piece) if capturable && (piece.ptype == PieceType::Pawn) && (piece.color == next_player.other()) => {
+                piece.en_passant = true;
+                Ok(())
+            },
+            _ => Err(format!("no pawn can be captured en passant on {}", square_name(passed as isize, col)))
+        }
+    }
+    pub fn can_capture_en_passant(&self, col: usize, next_player: Team) -> bool {
+        // a pawn of the side to move stands beside the file on the rank the double step ended on
+        let row = if next_player == Team::White { 3 } else { 4 };
+        [col.checked_sub(1), Some(col + 1)].iter().flatten().any(|&side| {
+            (side < COLS) && self.tiles[row][side].has_team(next_player) && (self.tiles[row][side].piece().ptype == PieceType::Pawn)
+        })
+    }
+    pub fn validate(&self, next_player: Team) -> Result<(), String> {
+        for color in [Team::White, Team::Black].iter() {
+            let kings = self.tiles.iter().flatten().filter(|tile| tile.has_team(*color) && (tile.piece().ptype == PieceType::King)).count();
+            if kings != 1 {
+                return Err(format!("{} needs exactly one king, found {}", color.as_string(), kings));
+            }
+        }
+        for row in [0, ROWS - 1].iter() {
+            if self.tiles[*row].iter().any(|tile| tile.has_piece() && (tile.piece().ptype == PieceType::Pawn)) {
+                return Err("pawns cannot stand on the first or last rank".to_string());
+            }
+        }
+        if self.get_pins_and_checks(next_player.other()).0 {
+            return Err(format!("{} is in check but it is not their move", next_player.other().as_string()));
+        }
+        return Ok(());
+    }
+    pub fn from_fen(fen: &str) -> Result<(Self, Team), String> {
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        if fields.len() < 2 {
+            return Err(format!("incomplete fen \"{}\"", fen));
+        }
+        let mut board = Self::empty();
+        let ranks: Vec<&str> = fields[0].split('/').collect();
+        if ranks.len() != ROWS {
+            return Err(format!("expected {} ranks in \"{}\"", ROWS, fields[0]));
+        }
+        for (row, rank) in ranks.iter().enumerate() {
+            let mut col = 0;
+            for c in rank.chars() {
+                if let Some(skip) = c.to_digit(10) {
+                    col += skip as usize;
+                    continue;
+                }
+                let ptype = PieceType::from_char(c).ok_or(format!("unknown piece '{}'", c))?;
+                if col >= COLS {
+                    return Err(format!("rank {} is too long", ROWS - row));
+                }
+                let color = if c.is_ascii_uppercase() { Team::White } else { Team::Black };
+                board.put_piece(row as isize, col as isize, Some((color, ptype)));
+                col += 1;
+            }
+            if col != COLS {
+                return Err(format!("rank {} does not have {} squares", ROWS - row, COLS));
+            }
+        }
+        let next_player = match fields[1] {
+            "w" => Team::White,
+            "b" => Team::Black,
+            other => return Err(format!("unknown side to move \"{}\"", other))
+        };
+        let castling = fields.get(2).copied().unwrap_or("-");
+        if !castling.chars().all(|c| "KQkq-".contains(c)) {
+            return Err(format!("invalid castling rights \"{}\"", castling));
+        }
+        let rights = [castling.contains('K'), castling.contains('Q'), castling.contains('k'), castling.contains('q')];
+        board.set_castling_rights(rights);
+        if board.castling_rights() != rights {
+            return Err(format!("castling rights \"{}\" need the king and rook on their starting squares", castling));
+        }
+        match fields.get(3).copied().unwrap_or("-") {
+            "-" => (),
+            square => {
+                let target_row = if next_player == Team::White { 2 } else { 5 };
+                let col = match parse_square(square) {
+                    Some((row, col)) if row == target_row => col,
+                    _ => return Err(format!("invalid en passant square \"{}\"", square))
+                };
+                // other programs name the square after every double step, it only counts when a pawn can take
+                if board.can_capture_en_passant(col as usize, next_player) {
+                    board.set_en_passant_file(Some(col), next_player)?;
+                }
+            }
+        }
+        board.validate(next_player)?;
+        return Ok((board, next_player));
+    }
+    pub fn to_fen(&self, next_player: Team, halfmove: usize, fullmove: usize) -> String {
+        let mut ranks = Vec::new();
+        for row in self.tiles.iter() {
+            let mut rank = String::new();
+            let mut empty = 0;
+            for tile in row.iter() {
+                match tile.present_piece {
+                    Some(piece) => {
+                        if empty > 0 {
+                            rank.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        let c = piece.ptype.as_char();
+                        rank.push(if piece.color == Team::White { c } else { c.to_ascii_lowercase() });
+                    },
+                    None => empty += 1
+                }
+            }
+            if empty > 0 {
+                rank.push_str(&empty.to_string());
+            }
+            ranks.push(rank);
+        }
+        let castling: String = self.castling_rights().iter().zip("KQkq".chars()).filter(|(allowed, _)| **allowed).map(|(_, c)| c).collect();
+        let en_passant = self.en_passant_target().map_or("-".to_string(), |(row, col)| square_name(row, col));
+        format!(
+            "{} {} {} {} {} {}",
+            ranks.join("/"),
+            if next_player == Team::White { "w" } else { "b" },
+            if castling.is_empty() { "-".to_string() } else { castling },
+            en_passant,
+            halfmove,
+            fullmove
+        )
+    }
+}
+
+
+pub fn fen_counters(fen: &str) -> (usize, usize) {
+    // the halfmove clock and fullmove number, which may be left out
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    let halfmove = fields.get(4).and_then(|field| field.parse().ok()).unwrap_or(0);
+    let fullmove = fields.get(5).and_then(|field| field.parse().ok()).unwrap_or(1);
+    return (halfmove, fullmove.max(1));
+}
+
+fn promotion_piece(c: char) -> Option<PieceType> {
+    match PieceType::from_char(c) {
+        Some(PieceType::Pawn) | Some(PieceType::King) => None,
+        ptype => ptype
+    }
 }
 
+fn san_core(san: &str) -> String {
+    // strips check marks, annotation glyphs and the promotion piece
+    let mut core = san.trim_end_matches(|c| "+#!?".contains(c)).replace('0', "O");
+    if let Some(idx) = core.find('=') {
+        core.truncate(idx);
+    }
+    return core;
+}
 
 fn add_pieces(tiles: &mut [[Tile; COLS]; ROWS], color: Team, start_uid: isize) -> isize {
     let (pawns, others): (usize, usize) = match color {
@@ -679,67 +1120,64 @@ fn add_pieces(tiles: &mut [[Tile; COLS]; ROWS], color: Team, start_uid: isize) -
 }
 
 
+fn see_value(ptype: PieceType) -> i32 {
+    match ptype {
+        PieceType::King => 20000,
+        _ => ptype.value_mg()
+    }
+}
+
+
+fn least_valuable_attacker(occupancy: &[[Option<Piece>; COLS]; ROWS], row: usize, col: usize, color: Team) -> Option<(usize, usize)> {
+    let mut best: Option<(usize, usize)> = None;
+    let mut consider = |pos: (isize, isize), ptypes: &[PieceType]| {
+        if let Some(piece) = occupancy[pos.0 as usize][pos.1 as usize] {
+            if (piece.color == color) && ptypes.contains(&piece.ptype) {
+                let better = match best {
+                    Some(b) => see_value(piece.ptype) < see_value(occupancy[b.0][b.1].unwrap().ptype),
+                    None => true
+                };
+                if better {
+                    best = Some((pos.0 as usize, pos.1 as usize));
+                }
+            }
+        }
+    };
+    let (row, col) = (row as isize, col as isize);
+
+    let pawn_row = if color == Team::White { row + 1 } else { row - 1 };
+    for c in [col - 1, col + 1] {
+        if inrange(pawn_row) && inrange(c) {
+            consider((pawn_row, c), &[PieceType::Pawn]);
+        }
+    }
+    for (dr, dc) in [(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)] {
+        if inrange(row + dr) && inrange(col + dc) {
+            consider((row + dr, col + dc), &[PieceType::Knight]);
+        }
+    }
+    for (dr, dc) in [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)] {
+        if inrange(row + dr) && inrange(col + dc) {
+            consider((row + dr, col + dc), &[PieceType::King]);
+        }
+        let sliders = if (dr == 0) || (dc == 0) { [PieceType::Rook, PieceType::Queen] } else { [PieceType::Bishop, PieceType::Queen] };
+        let (mut r, mut c) = (row + dr, col + dc);
+        while inrange(r) && inrange(c) {
+            if occupancy[r as usize][c as usize].is_some() {
+                consider((r, c), &sliders);
+                break;
+            }
+            r += dr;
+            c += dc;
+        }
+    }
+    return best;
+}
+
+
 fn psqt_bonuses(tiles: &mut [[Tile; COLS]; ROWS]) {
-    let pawns = [
-        [0.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0],
-        [50.0, 50.0, 50.0, 50.0, 50.0, 50.0, 50.0, 50.0],
-        [10.0, 10.0, 20.0, 30.0, 30.0, 20.0, 10.0, 10.0],
-        [5.0,  5.0, 10.0, 25.0, 25.0, 10.0,  5.0,  5.0],
-        [0.0,  0.0,  0.0, 20.0, 20.0,  0.0,  0.0,  0.0],
-        [5.0, -5.0,-10.0,  0.0,  0.0,-10.0, -5.0,  5.0],
-        [5.0, 10.0, 10.0,-20.0,-20.0, 10.0, 10.0,  5.0],
-        [0.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0]
-    ];
-    let knights = [
-        [-50.0,-40.0,-30.0,-30.0,-30.0,-30.0,-40.0,-50.0],
-        [-40.0,-20.0,  0.0,  0.0,  0.0,  0.0,-20.0,-40.0],
-        [-30.0,  0.0, 10.0, 15.0, 15.0, 10.0,  0.0,-30.0],
-        [-30.0,  5.0, 15.0, 20.0, 20.0, 15.0,  5.0,-30.0],
-        [-30.0,  0.0, 15.0, 20.0, 20.0, 15.0,  0.0,-30.0],
-        [-30.0,  5.0, 10.0, 15.0, 15.0, 10.0,  5.0,-30.0],
-        [-40.0,-20.0,  0.0,  5.0,  5.0,  0.0,-20.0,-40.0],
-        [-50.0,-40.0,-30.0,-30.0,-30.0,-30.0,-40.0,-50.0]
-    ];
-    let bishops = [
-        [-20.0,-10.0,-10.0,-10.0,-10.0,-10.0,-10.0,-20.0],
-        [-10.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0,-10.0],
-        [-10.0,  0.0,  5.0, 10.0, 10.0,  5.0,  0.0,-10.0],
-        [-10.0,  5.0,  5.0, 10.0, 10.0,  5.0,  5.0,-10.0],
-        [-10.0,  0.0, 10.0, 10.0, 10.0, 10.0,  0.0,-10.0],
-        [-10.0, 10.0, 10.0, 10.0, 10.0, 10.0, 10.0,-10.0],
-        [-10.0,  5.0,  0.0,  0.0,  0.0,  0.0,  5.0,-10.0],
-        [-20.0,-10.0,-10.0,-10.0,-10.0,-10.0,-10.0,-20.0]
-    ];
-    let rooks = [
-        [0.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0],
-        [5.0, 10.0, 10.0, 10.0, 10.0, 10.0, 10.0,  5.0],
-        [-5.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0, -5.0],
-        [-5.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0, -5.0],
-        [-5.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0, -5.0],
-        [-5.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0, -5.0],
-        [-5.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0, -5.0],
-        [0.0,  0.0,  0.0,  5.0,  5.0,  0.0,  0.0,  0.0]
-    ];
-    let queens = [
-        [-20.0,-10.0,-10.0, -5.0, -5.0,-10.0,-10.0,-20.0],
-        [-10.0,  0.0,  0.0,  0.0,  0.0,  0.0,  0.0,-10.0],
-        [-10.0,  0.0,  5.0,  5.0,  5.0,  5.0,  0.0,-10.0],
-        [-5.0,  0.0,  5.0,  5.0,  5.0,  5.0,  0.0, -5.0],
-        [0.0,  0.0,  5.0,  5.0,  5.0,  5.0,  0.0, -5.0],
-        [-10.0,  5.0,  5.0,  5.0,  5.0,  5.0,  0.0,-10.0],
-        [-10.0,  0.0,  5.0,  0.0,  0.0,  0.0,  0.0,-10.0],
-        [-20.0,-10.0,-10.0, -5.0, -5.0,-10.0,-10.0,-20.0]
-    ];
-    let kings = [
-        [-30.0,-40.0,-40.0,-50.0,-50.0,-40.0,-40.0,-30.0],
-        [-30.0,-40.0,-40.0,-50.0,-50.0,-40.0,-40.0,-30.0],
-        [-30.0,-40.0,-40.0,-50.0,-50.0,-40.0,-40.0,-30.0],
-        [-30.0,-40.0,-40.0,-50.0,-50.0,-40.0,-40.0,-30.0],
-        [-20.0,-30.0,-30.0,-40.0,-40.0,-30.0,-30.0,-20.0],
-        [-10.0,-20.0,-20.0,-20.0,-20.0,-20.0,-20.0,-10.0],
-        [20.0, 20.0,  0.0,  0.0,  0.0,  0.0, 20.0, 20.0],
-        [20.0, 30.0, 10.0,  0.0,  0.0, 10.0, 30.0, 20.0]
-    ];
+    // the tables live with the other evaluation parameters, so tuned values show up here too
+    let [pawns, knights, bishops, rooks, queens, kings] = EvalParams::active().psqt_mg.map(|table| table.map(|row| row.map(|value| value as f32)));
     for (i, row) in izip!(pawns, knights, bishops, rooks, queens, kings).enumerate() {
         for (j, (p, n, b, r, q, k)) in izip!(row.0, row.1, row.2, row.3, row.4, row.5).enumerate() {
             tiles[i][j].bonuses.insert((PieceType::Pawn, Team::White), p);
@@ -783,4 +1221,25 @@ fn create_zobrist_keys() -> [[u64; 12]; (ROWS * COLS)] {
         }
     }
     return zobrist;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_king_moves_in_double_check() {
+        let (mut board, _) = Board::from_fen("4r1k1/8/8/4n3/8/8/8/1N2K3 b - - 0 1").unwrap();
+        // lists from the position before the check must not survive it
+        board.calc_team_valid_moves(Team::White);
+        let (mut piece, action) = board.find_move(Team::Black, "e5f3").unwrap();
+        board.execute_move(&mut piece, action, false, false);
+        board.calc_team_valid_moves(Team::White);
+        let mut moves: Vec<String> = board.valid_moves.iter()
+            .filter(|(piece, _)| piece.color == Team::White)
+            .flat_map(|(_, moves)| moves.iter().map(|action| action.to_uci()))
+            .collect();
+        moves.sort();
+        assert_eq!(moves, ["e1d1", "e1f1", "e1f2"]);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,146 @@
+use macroquad::prelude::*;
+use std::fs;
+use super::game::*;
+
+const PGN_DIR: &str = "assets/pgns";
+const ROW_HEIGHT: f32 = 20.0;
+const LIST_TOP: f32 = 60.0;
+
+
+pub struct Browser {
+    files: Vec<String>,
+    games: Vec<PgnGame>,
+    // formatted once per file, the list is drawn every frame
+    game_rows: Vec<String>,
+    file: Option<usize>,
+    scroll: usize,
+    error: String
+}
+
+impl Browser {
+    pub fn new() -> Self {
+        let mut files: Vec<String> = match fs::read_dir(PGN_DIR) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.file_name().to_string_lossy().to_string()).collect(),
+            Err(err) => {
+                eprintln!("could not read {}: {}", PGN_DIR, err);
+                Vec::new()
+            }
+        };
+        files.sort();
+        Self {
+            files: files,
+            games: Vec::new(),
+            game_rows: Vec::new(),
+            file: None,
+            scroll: 0,
+            error: String::new()
+        }
+    }
+    pub async fn run(&mut self, game: &mut Game) -> bool {
+        // returns whether a game was opened, escape from the file list goes back to the menu
+        loop {
+            if is_key_pressed(KeyCode::Escape) {
+                if self.file.is_none() {
+                    return false;
+                }
+                self.file = None;
+                self.scroll = 0;
+                self.error.clear();
+            }
+            let wheel = mouse_wheel().1;
+            if wheel != 0.0 {
+                let max_scroll = self.rows().len().saturating_sub(self.visible_rows());
+                self.scroll = (self.scroll as isize - wheel.signum() as isize * 3).clamp(0, max_scroll as isize) as usize;
+            }
+            if is_mouse_button_pressed(MouseButton::Left) {
+                if let Some(i) = self.row_at(mouse_position()) {
+                    match self.file {
+                        None => self.open_file(i),
+                        Some(_) => {
+                            match game.view_pgn(&self.games[i]) {
+                                Ok(()) => return true,
+                                Err(err) => self.error = err
+                            }
+                        }
+                    }
+                }
+            }
+            self.draw(game.config.font);
+            next_frame().await;
+        }
+    }
+    fn open_file(&mut self, i: usize) {
+        let path = format!("{}/{}", PGN_DIR, self.files[i]);
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                self.games = parse_pgn(&text);
+                self.game_rows = self.games.iter().map(|pgn| game_row(pgn)).collect();
+                self.file = Some(i);
+                self.scroll = 0;
+                self.error.clear();
+            },
+            Err(err) => self.error = format!("could not read {}: {}", path, err)
+        }
+    }
+    fn rows(&self) -> &[String] {
+        match self.file {
+            None => &self.files,
+            Some(_) => &self.game_rows
+        }
+    }
+    fn visible_rows(&self) -> usize {
+        ((screen_height() - LIST_TOP - 30.0) / ROW_HEIGHT).max(1.0) as usize
+    }
+    fn row_at(&self, pos: (f32, f32)) -> Option<usize> {
+        if pos.1 < LIST_TOP - ROW_HEIGHT + 4.0 {
+            return None;
+        }
+        let row = ((pos.1 - (LIST_TOP - ROW_HEIGHT + 4.0)) / ROW_HEIGHT) as usize;
+        if row >= self.visible_rows() {
+            return None;
+        }
+        let i = row + self.scroll;
+        if i < self.rows().len() { Some(i) } else { None }
+    }
+    fn draw(&self, font: Font) {
+        clear_background(BG_COLOR);
+        let title = match self.file {
+            None => format!("{} ({} files)", PGN_DIR, self.files.len()),
+            Some(i) => format!("{} ({} games)", self.files[i], self.games.len())
+        };
+        draw_text_ex(&title, 10.0, 28.0, TextParams{font: font, font_size: 22u16, color: WHITE, ..Default::default()});
+        let hint = if self.file.is_none() { "click a file, Esc for the menu" } else { "click a game, Esc for the file list" };
+        draw_text_ex(hint, 10.0, screen_height() - 10.0, TextParams{font: font, font_size: 14u16, color: Color::from_rgba(150, 150, 150, 255), ..Default::default()});
+
+        let hovered = self.row_at(mouse_position());
+        let max_chars = ((screen_width() - 20.0) / 8.0).max(1.0) as usize;
+        for (i, row) in self.rows().iter().enumerate().skip(self.scroll).take(self.visible_rows()) {
+            let y = LIST_TOP + (i - self.scroll) as f32 * ROW_HEIGHT;
+            if hovered == Some(i) {
+                draw_rectangle(0.0, y - ROW_HEIGHT + 4.0, screen_width(), ROW_HEIGHT, Color::from_rgba(64, 61, 57, 255));
+            }
+            let text: String = row.chars().take(max_chars).collect();
+            draw_text_ex(&text, 10.0, y, TextParams{font: font, font_size: 14u16, color: Color::from_rgba(230, 230, 230, 255), ..Default::default()});
+        }
+        if !self.error.is_empty() {
+            draw_text_ex(&self.error, 10.0, 48.0, TextParams{font: font, font_size: 14u16, color: Color::from_rgba(220, 80, 80, 255), ..Default::default()});
+        }
+    }
+}
+
+
+fn game_row(pgn: &PgnGame) -> String {
+    // fixed width columns, the monospace font keeps them aligned
+    let tag = |name: &str| pgn.tag(name).unwrap_or("").to_string();
+    let fit = |text: String, width: usize| format!("{:<width$}", text.chars().take(width).collect::<String>(), width = width);
+    format!(
+        "{} {} {} {} {} {} {}",
+        fit(pgn.result.clone(), 7),
+        fit(tag("White"), 18),
+        fit(tag("WhiteElo"), 4),
+        fit(tag("Black"), 18),
+        fit(tag("BlackElo"), 4),
+        fit(tag("ECO"), 3),
+        tag("Event")
+    )
+}
\ No newline at end of file
@@ -1,7 +1,17 @@
 use macroquad::prelude::*;
 use macroquad::audio::{load_sound, Sound};
-#[path = "constants.rs"] mod constants;
-use constants::DEFAULT_THEME;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::process;
+use serde::Deserialize;
+use super::{Team, PieceType, Settings};
+
+const THEME_DIR: &str = "assets/themes";
+const SOUND_DIR: &str = "assets/sounds";
+const IMAGE_DIR: &str = "assets/images";
+const DEFAULT_PIECE_SET: &str = "default";
+const PIECE_SIZES: [usize; 2] = [80, 128];
 
 #[derive(Clone, Copy)]
 pub struct ColorPair {
@@ -18,8 +28,9 @@ impl ColorPair {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone)]
 pub struct Theme {
+    pub name: String,
     pub bg: ColorPair,
     pub trace: ColorPair,
     pub moves: ColorPair,
@@ -27,14 +38,216 @@ pub struct Theme {
 }
 
 impl Theme {
-    fn new(light_bg: Color, dark_bg: Color, light_trace: Color, dark_trace: Color, light_moves: Color, dark_moves: Color, title_color: Color) -> Self {
+    fn new(name: &str, light_bg: Color, dark_bg: Color, light_trace: Color, dark_trace: Color, light_moves: Color, dark_moves: Color, title_color: Color) -> Self {
         Self {
+            name: name.to_string(),
             bg: ColorPair::new(light_bg, dark_bg),
             trace: ColorPair::new(light_trace, dark_trace),
             moves: ColorPair::new(light_moves, dark_moves),
             title_color: title_color
         }
     }
+    pub fn all() -> Vec<Theme> {
+        // every file in the theme folder is a theme, sorted so the order is stable
+        let mut paths: Vec<_> = match fs::read_dir(THEME_DIR) {
+            Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+            Err(err) => {
+                eprintln!("could not read {}: {}", THEME_DIR, err);
+                Vec::new()
+            }
+        };
+        paths.sort();
+        let mut themes = Vec::new();
+        for path in paths.iter().filter(|path| path.extension().map_or(false, |ext| ext == "toml")) {
+            match Self::load(path) {
+                Ok(theme) => themes.push(theme),
+                Err(err) => eprintln!("skipping theme {}: {}", path.display(), err)
+            }
+        }
+        if themes.is_empty() {
+            themes.push(Self::fallback());
+        }
+        return themes;
+    }
+    fn load(path: &Path) -> Result<Theme, String> {
+        let text = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let file: ThemeFile = toml::from_str(&text).map_err(|err| err.to_string())?;
+        return Ok(Self::new(
+            &file.name,
+            parse_color(&file.light)?, parse_color(&file.dark)?,
+            parse_color(&file.light_trace)?, parse_color(&file.dark_trace)?,
+            parse_color(&file.light_moves)?, parse_color(&file.dark_moves)?,
+            parse_color(&file.title)?
+        ));
+    }
+    fn fallback() -> Theme {
+        Self::new(
+            "brown",
+            Color::from_rgba(235, 209, 166, 255), Color::from_rgba(165, 117, 80, 255),
+            Color::from_rgba(245, 234, 100, 255), Color::from_rgba(209, 185, 59, 255),
+            Color::from_rgba(200, 100, 100, 255), Color::from_rgba(200, 70, 70, 255),
+            Color::from_rgba(230, 230, 230, 255)
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct ThemeFile {
+    name: String,
+    light: String,
+    dark: String,
+    light_trace: String,
+    dark_trace: String,
+    light_moves: String,
+    dark_moves: String,
+    title: String
+}
+
+fn parse_color(text: &str) -> Result<Color, String> {
+    // "#rrggbb" or "#rrggbbaa"
+    let hex = text.trim_start_matches('#');
+    if ((hex.len() != 6) && (hex.len() != 8)) || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("invalid colour \"{}\"", text));
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap();
+    let alpha = if hex.len() == 8 { channel(6) } else { 255 };
+    return Ok(Color::from_rgba(channel(0), channel(2), channel(4), alpha));
+}
+
+#[derive(Eq, Hash, PartialEq, Clone, Copy)]
+pub enum SoundEvent {
+    Move,
+    Capture,
+    Check,
+    Castle,
+    Promote,
+    GameEnd,
+    Illegal,
+    LowTime
+}
+
+impl SoundEvent {
+    fn all() -> [SoundEvent; 8] {
+        [Self::Move, Self::Capture, Self::Check, Self::Castle, Self::Promote, Self::GameEnd, Self::Illegal, Self::LowTime]
+    }
+    fn file_name(&self) -> &'static str {
+        match *self {
+            Self::Move => "move.wav",
+            Self::Capture => "capture.wav",
+            Self::Check => "check.wav",
+            Self::Castle => "castle.wav",
+            Self::Promote => "promote.wav",
+            Self::GameEnd => "game_end.wav",
+            Self::Illegal => "illegal.wav",
+            Self::LowTime => "low_time.wav"
+        }
+    }
+    fn fallback(&self) -> Option<SoundEvent> {
+        // events without their own file borrow the closest sound that exists
+        match *self {
+            Self::Move | Self::Illegal => None,
+            _ => Some(Self::Move)
+        }
+    }
+}
+
+
+
+pub struct TextureAtlas {
+    sets: Vec<(usize, HashMap<(Team, PieceType), Texture2D>)>
+}
+
+impl TextureAtlas {
+    pub async fn load(set: &str) -> Result<Self, String> {
+        // the default set comes in two resolutions, other sets are a single folder of images
+        let dirs: Vec<String> = if set == DEFAULT_PIECE_SET {
+            PIECE_SIZES.iter().map(|size| format!("{}/imgs-{}px", IMAGE_DIR, size)).collect()
+        }
+        else {
+            Vec::from([format!("{}/{}", IMAGE_DIR, set)])
+        };
+        let mut sets = Vec::new();
+        for dir in dirs.iter() {
+            let mut textures = HashMap::new();
+            for color in [Team::White, Team::Black].iter() {
+                for ptype in [PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen, PieceType::King].iter() {
+                    let path = find_piece_image(dir, *color, *ptype)?;
+                    let texture = load_texture(&path).await.map_err(|err| format!("could not load {}: {}", path, err))?;
+                    texture.set_filter(FilterMode::Linear);
+                    textures.insert((*color, *ptype), texture);
+                }
+            }
+            let size = textures[&(Team::White, PieceType::Pawn)].width() as usize;
+            sets.push((size, textures));
+        }
+        sets.sort_by_key(|(size, _)| *size);
+        return Ok(Self {
+            sets: sets
+        });
+    }
+    pub async fn load_or_default(set: &str) -> Result<Self, String> {
+        match Self::load(set).await {
+            Ok(atlas) => Ok(atlas),
+            Err(err) if set != DEFAULT_PIECE_SET => {
+                eprintln!("falling back to the default pieces: {}", err);
+                Self::load(DEFAULT_PIECE_SET).await
+            },
+            Err(err) => Err(err)
+        }
+    }
+    pub fn piece_sets() -> Vec<String> {
+        // any folder of images other than the resolutions of the default set
+        let mut sets = Vec::new();
+        if let Ok(entries) = fs::read_dir(IMAGE_DIR) {
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.path().is_dir() && !name.starts_with("imgs-") {
+                    sets.push(name);
+                }
+            }
+        }
+        sets.sort();
+        sets.insert(0, DEFAULT_PIECE_SET.to_string());
+        return sets;
+    }
+    pub fn get(&self, color: Team, ptype: PieceType, tilesize: f32) -> Option<Texture2D> {
+        // the smallest set that does not need upscaling looks the sharpest
+        let set = self.sets.iter().find(|(size, _)| *size as f32 >= tilesize).or(self.sets.last())?;
+        set.1.get(&(color, ptype)).copied()
+    }
+}
+
+fn find_piece_image(dir: &str, color: Team, ptype: PieceType) -> Result<String, String> {
+    // the sets do not agree on capitalisation, so both spellings are accepted
+    let candidates = [
+        format!("{}/{}_{}.png", dir, color.as_string(), ptype.as_string()),
+        format!("{}/{}_{}.png", dir, color.as_string(), ptype.as_string()).to_lowercase()
+    ];
+    match candidates.iter().find(|path| Path::new(path).exists()) {
+        Some(path) => Ok(path.clone()),
+        None => Err(format!("missing piece image {}", candidates[0]))
+    }
+}
+
+async fn load_sounds() -> HashMap<SoundEvent, Sound> {
+    // sounds are optional, a missing file falls back to another event's sound
+    let mut sounds = HashMap::new();
+    for event in SoundEvent::all().iter() {
+        let path = format!("{}/{}", SOUND_DIR, event.file_name());
+        if !Path::new(&path).exists() {
+            continue;
+        }
+        match load_sound(&path).await {
+            Ok(sound) => { sounds.insert(*event, sound); },
+            Err(err) => eprintln!("could not load {}: {}", path, err)
+        }
+    }
+    return sounds;
+}
+
+fn asset_error(err: String) -> ! {
+    eprintln!("error: {}", err);
+    process::exit(1);
 }
 
 
@@ -43,49 +256,62 @@ pub struct Config {
     pub idx: usize,
     pub theme: Theme,
     pub themes: Vec<Theme>,
+    pub settings: Settings,
     pub font: Font,
-    pub move_sound: Sound,
-    pub capture_sound: Sound
+    pub pieces: TextureAtlas,
+    pub sounds: HashMap<SoundEvent, Sound>
 }
 
 impl Config {
     pub async fn new() -> Self {
-        let green = Theme::new(
-            Color::from_rgba(234, 235, 200, 255), Color::from_rgba(119, 154, 88, 255),
-            Color::from_rgba(244, 247, 116, 255), Color::from_rgba(172, 19, 51, 255),
-            Color::from_rgba(200, 100, 100, 255), Color::from_rgba(200, 70, 70, 255),
-            Color::from_rgba(230, 230, 230, 255)
-        );
-        let brown = Theme::new(
-            Color::from_rgba(235, 209, 166, 255), Color::from_rgba(165, 117, 80, 255),
-            Color::from_rgba(245, 234, 100, 255), Color::from_rgba(209, 185, 59, 255),
-            Color::from_rgba(200, 100, 100, 255), Color::from_rgba(200, 70, 70, 255),
-            Color::from_rgba(230, 230, 230, 255)
-        );
-        let blue = Theme::new(
-            Color::from_rgba(229, 228, 200, 255), Color::from_rgba(60, 95, 135, 255),
-            Color::from_rgba(123, 187, 227, 255), Color::from_rgba(43, 119, 191, 255),
-            Color::from_rgba(200, 100, 100, 255), Color::from_rgba(200, 70, 70, 255),
-            Color::from_rgba(230, 230, 230, 255)
-        );
-        let gray = Theme::new(
-            Color::from_rgba(234, 235, 200, 255), Color::from_rgba(119, 154, 88, 255),
-            Color::from_rgba(99, 126, 143, 255), Color::from_rgba(82, 102, 128, 255),
-            Color::from_rgba(200, 100, 100, 255), Color::from_rgba(200, 70, 70, 255),
-            Color::from_rgba(230, 230, 230, 255)
-        );
-        let themes = Vec::from([green, brown, blue, gray]);
+        let settings = Settings::load();
+        let themes = Theme::all();
+        let idx = themes.iter().position(|theme| theme.name == settings.theme).unwrap_or(0);
         Self {
-            idx: DEFAULT_THEME,
-            theme: themes[DEFAULT_THEME],
+            idx: idx,
+            theme: themes[idx].clone(),
             themes: themes,
-            font: load_ttf_font("assets/Monaco.ttf").await.unwrap(),
-            move_sound: load_sound("assets/sounds/move.wav").await.unwrap(),
-            capture_sound: load_sound("assets/sounds/capture.wav").await.unwrap(),
+            font: load_ttf_font("assets/Monaco.ttf").await.unwrap_or_else(|err| asset_error(format!("could not load assets/Monaco.ttf: {}", err))),
+            pieces: TextureAtlas::load_or_default(&settings.piece_set).await.unwrap_or_else(|err| asset_error(err)),
+            sounds: load_sounds().await,
+            settings: settings,
+        }
+    }
+    pub fn sound(&self, event: SoundEvent) -> Option<Sound> {
+        match self.sounds.get(&event) {
+            Some(sound) => Some(*sound),
+            None => self.sound(event.fallback()?)
         }
     }
     pub fn change_theme(&mut self) {
         self.idx = (self.idx + 1) % self.themes.len();
-        self.theme = self.themes[self.idx];
+        self.theme = self.themes[self.idx].clone();
+        self.settings.theme = self.theme.name.clone();
+        self.settings.save();
+    }
+    pub async fn change_piece_set(&mut self) {
+        let sets = TextureAtlas::piece_sets();
+        let idx = sets.iter().position(|set| *set == self.settings.piece_set).map_or(0, |idx| (idx + 1) % sets.len());
+        let mut settings = self.settings.clone();
+        settings.piece_set = sets[idx].clone();
+        self.apply_settings(settings).await;
+        // a set that failed to load is not remembered
+        if self.settings.piece_set == sets[idx] {
+            self.settings.save();
+        }
+    }
+    pub async fn apply_settings(&mut self, mut settings: Settings) {
+        self.idx = self.themes.iter().position(|theme| theme.name == settings.theme).unwrap_or(self.idx);
+        self.theme = self.themes[self.idx].clone();
+        if settings.piece_set != self.settings.piece_set {
+            match TextureAtlas::load(&settings.piece_set).await {
+                Ok(atlas) => self.pieces = atlas,
+                Err(err) => {
+                    eprintln!("keeping the current pieces: {}", err);
+                    settings.piece_set = self.settings.piece_set.clone();
+                }
+            }
+        }
+        self.settings = settings;
     }
 }
\ No newline at end of file
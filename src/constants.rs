@@ -3,7 +3,17 @@ use macroquad::prelude::Color;
 
 pub const ROWS: usize = 8;
 pub const COLS: usize = 8;
-pub const DEFAULT_THEME: usize = 1;
+pub const SEARCH_DEPTH: isize = 4;
+pub const MAX_MULTI_PV: usize = 5;
+pub const ANALYSIS_DEPTH: isize = 6;
+pub const EVAL_BAR_WIDTH: f32 = 16.0;
+pub const LOW_TIME: u128 = 10000;
+pub const SAVED_PGN: &str = "saves/game.pgn";
+pub const AUTOSAVE_PGN: &str = "saves/autosave.pgn";
+pub const REVIEWED_PGN: &str = "saves/reviews/game.pgn";
+pub const DRAW_ACCEPT_MARGIN: f32 = 0.5;
+pub const NOTICE_TIME: u128 = 3000;
+pub const MAX_COMMENT_LINES: usize = 6;
 pub const BG_COLOR: Color = Color::new(0.13, 0.125, 0.13, 1.0);
 
 // pub const ZOBRIST_FILE: String = String::from("internal/zobrist.bin");
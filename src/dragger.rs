@@ -1,4 +1,3 @@
-use macroquad::prelude::*;
 #[path = "board.rs"] mod board;
 pub use board::*;
 
@@ -23,26 +22,11 @@ impl Dragger {
             init_col: 0
         }
     }
-    pub async fn draw(&mut self) {
-        let piece: &mut Piece; 
-        match &mut self.piece {
-            Some(val) => {piece = val},
-            None => {return}
-        };
-        let im = load_texture(&piece.texture_path(128)).await.unwrap();
-        draw_texture(
-            im, 
-            self.mousex - im.width() / 2.0,
-            self.mousey - im.height() / 2.0,
-            Color::new(1.0, 1.0, 1.0, 1.0),
-        )
-    }
     pub fn update_mouse(&mut self, position: (f32, f32)) {
         (self.mousex, self.mousey) = position;
     }
-    pub fn save_initial(&mut self, position: (f32, f32), tilesize: f32) {
-        self.init_row = (position.1 / tilesize) as isize;
-        self.init_col = (position.0 / tilesize) as isize;
+    pub fn save_initial(&mut self, position: (f32, f32), tilesize: f32, flipped: bool) {
+        (self.init_row, self.init_col) = screen_to_tile(position, tilesize, flipped);
     }
     pub fn begin_drag(&mut self, piece: &Piece) {
         self.piece = Some(*piece);
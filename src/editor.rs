@@ -0,0 +1,303 @@
+use macroquad::prelude::*;
+use super::game::*;
+use super::menu::Button;
+
+const PALETTE: [PieceType; 6] = [PieceType::King, PieceType::Queen, PieceType::Rook, PieceType::Bishop, PieceType::Knight, PieceType::Pawn];
+const CASTLING_LABELS: [&str; 4] = ["W O-O", "W O-O-O", "B O-O", "B O-O-O"];
+const ROW_HEIGHT: f32 = 24.0;
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum SetupAction {
+    PlayAi,
+    PlayPvp,
+    Analyse
+}
+
+
+pub struct Editor {
+    board: Board,
+    next_player: Team,
+    castling: [bool; 4],
+    en_passant: Option<isize>,
+    brush: (Team, PieceType),
+    error: String
+}
+
+impl Editor {
+    pub fn new() -> Self {
+        Self {
+            board: Board::new(),
+            next_player: Team::White,
+            castling: [true; 4],
+            en_passant: None,
+            brush: (Team::White, PieceType::Pawn),
+            error: String::new()
+        }
+    }
+    pub async fn run(&mut self, game: &mut Game) -> Option<(String, SetupAction)> {
+        loop {
+            let tilesize = screen_width().min(screen_height()) / 8.0;
+            if is_key_pressed(KeyCode::Escape) {
+                return None;
+            }
+            if is_mouse_button_pressed(MouseButton::Left) {
+                let mousepos = mouse_position();
+                let (row, col) = screen_to_tile(mousepos, tilesize, game.flipped);
+                if inrange(row) && inrange(col) && (mousepos.0 < COLS as f32 * tilesize) {
+                    self.paint(row, col);
+                }
+                else if let Some(brush) = self.palette_at(mousepos, tilesize) {
+                    self.brush = brush;
+                }
+                else if let Some(i) = self.buttons(game.config.font, tilesize).iter_mut().position(|button| button.clicked(mousepos)) {
+                    let action = match i {
+                        8 => Some(SetupAction::PlayAi),
+                        9 => Some(SetupAction::PlayPvp),
+                        10 => Some(SetupAction::Analyse),
+                        11 => return None,
+                        _ => None
+                    };
+                    match action {
+                        Some(action) => {
+                            if let Some(start) = self.start(action) {
+                                return Some(start);
+                            }
+                        },
+                        None => self.on_click(i)
+                    }
+                }
+            }
+            if is_mouse_button_pressed(MouseButton::Right) {
+                let (row, col) = screen_to_tile(mouse_position(), tilesize, game.flipped);
+                if inrange(row) && inrange(col) {
+                    self.board.put_piece(row, col, None);
+                    self.refresh_rights();
+                }
+            }
+            self.draw(game, tilesize);
+            next_frame().await;
+        }
+    }
+    fn paint(&mut self, row: isize, col: isize) {
+        // clicking a square that already holds the brush piece clears it
+        let current = self.board.tiles[row as usize][col as usize].present_piece.map(|piece| (piece.color, piece.ptype));
+        let piece = if current == Some(self.brush) { None } else { Some(self.brush) };
+        self.board.put_piece(row, col, piece);
+        self.refresh_rights();
+    }
+    fn refresh_rights(&mut self) {
+        // rights that the placement no longer allows are dropped
+        self.board.set_castling_rights(self.castling);
+        self.castling = self.board.castling_rights();
+        if self.board.set_en_passant_file(self.en_passant, self.next_player).is_err() {
+            self.en_passant = None;
+        }
+        self.error.clear();
+    }
+    fn next_en_passant(&self) -> Option<isize> {
+        // cycles through the files where a pawn could be taken en passant
+        let mut board = self.board.copy();
+        let start = self.en_passant.map_or(0, |col| col + 1);
+        (start..COLS as isize).find(|col| board.set_en_passant_file(Some(*col), self.next_player).is_ok())
+    }
+    pub fn fen(&self) -> String {
+        self.board.to_fen(self.next_player, 0, 1)
+    }
+    fn start(&mut self, action: SetupAction) -> Option<(String, SetupAction)> {
+        let fen = self.fen();
+        match Board::from_fen(&fen) {
+            Ok(_) => Some((fen, action)),
+            Err(err) => {
+                self.error = err;
+                None
+            }
+        }
+    }
+    fn on_click(&mut self, i: usize) {
+        match i {
+            0 => self.next_player = self.next_player.other(),
+            1..=4 => self.castling[i - 1] = !self.castling[i - 1],
+            5 => self.en_passant = self.next_en_passant(),
+            6 => self.board = Board::empty(),
+            7 => {
+                self.board = Board::new();
+                self.castling = [true; 4];
+            },
+            _ => ()
+        }
+        self.refresh_rights();
+    }
+    fn panel(&self, tilesize: f32) -> (f32, f32) {
+        let x = COLS as f32 * tilesize + 10.0;
+        return (x, (screen_width() - x - 10.0).max(0.0));
+    }
+    fn icon_size(&self, tilesize: f32) -> f32 {
+        (self.panel(tilesize).1 / PALETTE.len() as f32).min(40.0)
+    }
+    fn palette_at(&self, pos: (f32, f32), tilesize: f32) -> Option<(Team, PieceType)> {
+        let (x, _) = self.panel(tilesize);
+        let size = self.icon_size(tilesize);
+        let col = ((pos.0 - x) / size).floor();
+        let row = ((pos.1 - 10.0) / size).floor();
+        if (col < 0.0) || (col >= PALETTE.len() as f32) || (row < 0.0) || (row >= 2.0) {
+            return None;
+        }
+        let color = if row == 0.0 { Team::White } else { Team::Black };
+        return Some((color, PALETTE[col as usize]));
+    }
+    fn buttons(&self, font: Font, tilesize: f32) -> Vec<Button> {
+        let (x, width) = self.panel(tilesize);
+        let castling = self.board.castling_rights();
+        let on_off = |value: bool| if value { "on" } else { "off" };
+        let en_passant = self.en_passant.map_or("-".to_string(), |col| ((b'a' + col as u8) as char).to_string());
+        let rows = Vec::from([
+            Vec::from([format!("{} to move", self.next_player.as_string())]),
+            Vec::from([format!("{} {}", CASTLING_LABELS[0], on_off(castling[0])), format!("{} {}", CASTLING_LABELS[1], on_off(castling[1]))]),
+            Vec::from([format!("{} {}", CASTLING_LABELS[2], on_off(castling[2])), format!("{} {}", CASTLING_LABELS[3], on_off(castling[3]))]),
+            Vec::from([format!("En passant: {}", en_passant)]),
+            Vec::from(["Clear".to_string(), "Initial".to_string()]),
+            Vec::from(["Play AI".to_string(), "Play PVP".to_string()]),
+            Vec::from(["Analyse".to_string(), "Back".to_string()]),
+        ]);
+        let top = 20.0 + 2.0 * self.icon_size(tilesize);
+        let mut buttons = Vec::new();
+        for (i, row) in rows.into_iter().enumerate() {
+            let n = row.len() as f32;
+            let button_width = (width - 4.0 * (n - 1.0)) / n;
+            for (j, text) in row.into_iter().enumerate() {
+                let pos = (x + j as f32 * (button_width + 4.0), top + i as f32 * (ROW_HEIGHT + 4.0));
+                buttons.push(Button::new(pos, (button_width, ROW_HEIGHT), text, font));
+            }
+        }
+        return buttons;
+    }
+    fn draw(&self, game: &mut Game, tilesize: f32) {
+        clear_background(BG_COLOR);
+        game.render_bg(tilesize);
+        for row in 0..ROWS {
+            for col in 0..COLS {
+                if let Some(piece) = self.board.tiles[row][col].present_piece {
+                    game.render_piece(&piece, tile_to_screen(row as isize, col as isize, tilesize, game.flipped), tilesize, 1.0);
+                }
+            }
+        }
+
+        let (x, width) = self.panel(tilesize);
+        let size = self.icon_size(tilesize);
+        for (i, color) in [Team::White, Team::Black].iter().enumerate() {
+            for (j, ptype) in PALETTE.iter().enumerate() {
+                let pos = (x + j as f32 * size, 10.0 + i as f32 * size);
+                if self.brush == (*color, *ptype) {
+                    draw_rectangle(pos.0, pos.1, size, size, Color::from_rgba(200, 200, 200, 120));
+                }
+                game.render_piece(&Piece::new(*ptype, *color, -1, 0, 0), pos, size, 1.0);
+            }
+        }
+        let buttons = self.buttons(game.config.font, tilesize);
+        for button in buttons.iter() {
+            button.draw();
+        }
+
+        // the fen wraps to the panel, the editor only has the space below the buttons
+        let top = 30.0 + 2.0 * size + 7.0 * (ROW_HEIGHT + 4.0);
+        let char_width = measure_text("0", Some(game.config.font), 13u16, 1.0).width.max(1.0);
+        let per_line = ((width / char_width) as usize).max(1);
+        let mut lines: Vec<(String, Color)> = Vec::new();
+        for text in [(self.fen(), game.config.theme.title_color), (self.error.clone(), Color::from_rgba(220, 80, 80, 255))].iter() {
+            let chars: Vec<char> = text.0.chars().collect();
+            for chunk in chars.chunks(per_line) {
+                lines.push((chunk.iter().collect(), text.1));
+            }
+        }
+        for (i, (line, color)) in lines.iter().enumerate() {
+            draw_text_ex(
+                line,
+                x,
+                top + i as f32 * 16.0,
+                TextParams{font: game.config.font, font_size: 13u16, color: *color, ..Default::default()}
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+    #[test]
+    fn starts_from_the_initial_position() {
+        assert_eq!(Editor::new().fen(), START);
+    }
+
+    #[test]
+    fn fen_round_trips() {
+        for fen in [
+            START,
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+            "r3k2r/8/8/8/8/8/8/R3K2R w Kq - 4 20",
+            "8/8/4k3/8/8/4K3/8/8 b - - 0 1"
+        ] {
+            let (board, next_player) = Board::from_fen(fen).unwrap();
+            let (halfmove, fullmove) = fen_counters(fen);
+            assert_eq!(board.to_fen(next_player, halfmove, fullmove), fen);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_setups() {
+        for fen in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQ1BNR w kq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNK w kq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/1NBQKBNR w KQkq - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq e3 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KXq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/4B3/PPPP1PPP/RN1QKBNR b KQkq e3 0 3",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNZ w KQkq - 0 1",
+            "P3k3/8/8/8/8/8/8/4K3 w - - 0 1",
+            "4k3/4R3/8/8/8/8/8/4K3 w - - 0 1"
+        ] {
+            assert!(Board::from_fen(fen).is_err(), "{} should be rejected", fen);
+        }
+    }
+
+    #[test]
+    fn drops_rights_the_placement_no_longer_allows() {
+        let mut editor = Editor::new();
+        editor.board.put_piece(7, 7, None);
+        editor.refresh_rights();
+        assert_eq!(editor.castling, [false, true, true, true]);
+        assert!(editor.fen().contains(" w Qkq - "));
+
+        editor.board.put_piece(0, 4, None);
+        editor.board.put_piece(0, 3, Some((Team::Black, PieceType::King)));
+        editor.refresh_rights();
+        assert_eq!(editor.castling, [false, true, false, false]);
+    }
+
+    #[test]
+    fn offers_only_capturable_en_passant_files() {
+        let mut editor = Editor::new();
+        assert_eq!(editor.next_en_passant(), None);
+        editor.board.put_piece(6, 4, None);
+        editor.board.put_piece(4, 4, Some((Team::White, PieceType::Pawn)));
+        editor.next_player = Team::Black;
+        editor.refresh_rights();
+        assert_eq!(editor.next_en_passant(), None);
+        editor.board.put_piece(1, 3, None);
+        editor.board.put_piece(4, 3, Some((Team::Black, PieceType::Pawn)));
+        assert_eq!(editor.next_en_passant(), Some(4));
+        editor.board.put_piece(5, 4, Some((Team::White, PieceType::Knight)));
+        assert_eq!(editor.next_en_passant(), None);
+    }
+
+    #[test]
+    fn drops_en_passant_squares_no_pawn_can_take() {
+        let (board, next_player) = Board::from_fen("4k3/8/8/8/4P3/8/8/4K3 b - e3 0 1").unwrap();
+        assert_eq!(board.to_fen(next_player, 0, 1), "4k3/8/8/8/4P3/8/8/4K3 b - - 0 1");
+    }
+}
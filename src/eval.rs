@@ -0,0 +1,341 @@
+use std::fs;
+use std::sync::OnceLock;
+use serde::{Serialize, Deserialize};
+use super::{Board, PieceType, Team};
+
+// written by the tuner and read once, the first time the engine evaluates a position
+pub const EVAL_FILE: &str = "saves/eval.toml";
+// knights and bishops count one, rooks two and queens four, so the starting position is 24
+const MAX_PHASE: i32 = 24;
+const PHASE_WEIGHTS: [i32; 5] = [0, 1, 1, 2, 4];
+const PSQT_MG_OFFSET: usize = 10;
+const PSQT_EG_OFFSET: usize = PSQT_MG_OFFSET + 6 * 64;
+const BISHOP_PAIR_IDX: usize = PSQT_EG_OFFSET + 6 * 64;
+pub const N_PARAMS: usize = BISHOP_PAIR_IDX + 1;
+const DEFAULT_PSQT: [[[i32; 8]; 8]; 6] = [
+    // pawns
+    [
+        [  0,   0,   0,   0,   0,   0,   0,   0],
+        [ 50,  50,  50,  50,  50,  50,  50,  50],
+        [ 10,  10,  20,  30,  30,  20,  10,  10],
+        [  5,   5,  10,  25,  25,  10,   5,   5],
+        [  0,   0,   0,  20,  20,   0,   0,   0],
+        [  5,  -5, -10,   0,   0, -10,  -5,   5],
+        [  5,  10,  10, -20, -20,  10,  10,   5],
+        [  0,   0,   0,   0,   0,   0,   0,   0]
+    ],
+    // knights
+    [
+        [-50, -40, -30, -30, -30, -30, -40, -50],
+        [-40, -20,   0,   0,   0,   0, -20, -40],
+        [-30,   0,  10,  15,  15,  10,   0, -30],
+        [-30,   5,  15,  20,  20,  15,   5, -30],
+        [-30,   0,  15,  20,  20,  15,   0, -30],
+        [-30,   5,  10,  15,  15,  10,   5, -30],
+        [-40, -20,   0,   5,   5,   0, -20, -40],
+        [-50, -40, -30, -30, -30, -30, -40, -50]
+    ],
+    // bishops
+    [
+        [-20, -10, -10, -10, -10, -10, -10, -20],
+        [-10,   0,   0,   0,   0,   0,   0, -10],
+        [-10,   0,   5,  10,  10,   5,   0, -10],
+        [-10,   5,   5,  10,  10,   5,   5, -10],
+        [-10,   0,  10,  10,  10,  10,   0, -10],
+        [-10,  10,  10,  10,  10,  10,  10, -10],
+        [-10,   5,   0,   0,   0,   0,   5, -10],
+        [-20, -10, -10, -10, -10, -10, -10, -20]
+    ],
+    // rooks
+    [
+        [  0,   0,   0,   0,   0,   0,   0,   0],
+        [  5,  10,  10,  10,  10,  10,  10,   5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [ -5,   0,   0,   0,   0,   0,   0,  -5],
+        [  0,   0,   0,   5,   5,   0,   0,   0]
+    ],
+    // queens
+    [
+        [-20, -10, -10,  -5,  -5, -10, -10, -20],
+        [-10,   0,   0,   0,   0,   0,   0, -10],
+        [-10,   0,   5,   5,   5,   5,   0, -10],
+        [ -5,   0,   5,   5,   5,   5,   0,  -5],
+        [  0,   0,   5,   5,   5,   5,   0,  -5],
+        [-10,   5,   5,   5,   5,   5,   0, -10],
+        [-10,   0,   5,   0,   0,   0,   0, -10],
+        [-20, -10, -10,  -5,  -5, -10, -10, -20]
+    ],
+    // kings
+    [
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-30, -40, -40, -50, -50, -40, -40, -30],
+        [-20, -30, -30, -40, -40, -30, -30, -20],
+        [-10, -20, -20, -20, -20, -20, -20, -10],
+        [ 20,  20,   0,   0,   0,   0,  20,  20],
+        [ 20,  30,  10,   0,   0,  10,  30,  20]
+    ]
+];
+// once the queens are off the king belongs in the centre
+const DEFAULT_KING_EG: [[i32; 8]; 8] = [
+    [-50, -40, -30, -20, -20, -30, -40, -50],
+    [-30, -20, -10,   0,   0, -10, -20, -30],
+    [-30, -10,  20,  30,  30,  20, -10, -30],
+    [-30, -10,  30,  40,  40,  30, -10, -30],
+    [-30, -10,  30,  40,  40,  30, -10, -30],
+    [-30, -10,  20,  30,  30,  20, -10, -30],
+    [-30, -30,   0,   0,   0,   0, -30, -30],
+    [-50, -30, -30, -30, -30, -30, -30, -50]
+];
+
+static ACTIVE: OnceLock<EvalParams> = OnceLock::new();
+
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EvalParams {
+    // pawn, knight, bishop, rook and queen, blended from middlegame to endgame values by the material left
+    pub material_mg: [i32; 5],
+    pub material_eg: [i32; 5],
+    // pawn to king, as seen by white with the eighth rank first, blended like the material
+    pub psqt_mg: [[[i32; 8]; 8]; 6],
+    pub psqt_eg: [[[i32; 8]; 8]; 6],
+    pub bishop_pair: i32
+}
+
+impl Default for EvalParams {
+    fn default() -> Self {
+        let pieces = [PieceType::Pawn, PieceType::Knight, PieceType::Bishop, PieceType::Rook, PieceType::Queen];
+        let mut psqt_eg = DEFAULT_PSQT;
+        psqt_eg[5] = DEFAULT_KING_EG;
+        Self {
+            material_mg: pieces.map(|ptype| ptype.value_mg()),
+            material_eg: pieces.map(|ptype| ptype.value_eg()),
+            psqt_mg: DEFAULT_PSQT,
+            psqt_eg: psqt_eg,
+            bishop_pair: 0
+        }
+    }
+}
+
+impl EvalParams {
+    pub fn active() -> &'static Self {
+        ACTIVE.get_or_init(|| {
+            if !std::path::Path::new(EVAL_FILE).exists() {
+                return Self::default();
+            }
+            match Self::load(EVAL_FILE) {
+                Ok(params) => params,
+                Err(err) => {
+                    eprintln!("using the built-in evaluation: {}", err);
+                    Self::default()
+                }
+            }
+        })
+    }
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+        toml::from_str(&text).map_err(|err| format!("invalid {}: {}", path, err))
+    }
+    pub fn save(&self, path: &str) -> Result<(), String> {
+        let text = toml::to_string(self).map_err(|err| format!("could not serialize {}: {}", path, err))?;
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        fs::write(path, text).map_err(|err| format!("could not write {}: {}", path, err))
+    }
+    pub fn to_vector(&self) -> Vec<f64> {
+        let mut weights = vec![0.0; N_PARAMS];
+        for i in 0..5 {
+            weights[i] = self.material_mg[i] as f64;
+            weights[5 + i] = self.material_eg[i] as f64;
+        }
+        for (offset, tables) in [(PSQT_MG_OFFSET, &self.psqt_mg), (PSQT_EG_OFFSET, &self.psqt_eg)] {
+            for (p, table) in tables.iter().enumerate() {
+                for (row, values) in table.iter().enumerate() {
+                    for (col, value) in values.iter().enumerate() {
+                        weights[offset + p * 64 + row * 8 + col] = *value as f64;
+                    }
+                }
+            }
+        }
+        weights[BISHOP_PAIR_IDX] = self.bishop_pair as f64;
+        return weights;
+    }
+    pub fn from_vector(weights: &[f64]) -> Self {
+        let mut params = Self::default();
+        for i in 0..5 {
+            params.material_mg[i] = weights[i].round() as i32;
+            params.material_eg[i] = weights[5 + i].round() as i32;
+        }
+        for p in 0..6 {
+            for row in 0..8 {
+                for col in 0..8 {
+                    params.psqt_mg[p][row][col] = weights[PSQT_MG_OFFSET + p * 64 + row * 8 + col].round() as i32;
+                    params.psqt_eg[p][row][col] = weights[PSQT_EG_OFFSET + p * 64 + row * 8 + col].round() as i32;
+                }
+            }
+        }
+        params.bishop_pair = weights[BISHOP_PAIR_IDX].round() as i32;
+        return params;
+    }
+    pub fn pawn_value(&self) -> f32 {
+        // search scores are in the units of these weights, a middlegame pawn is a hundred centipawns
+        (self.material_mg[0] as f32).max(1.0)
+    }
+    pub fn to_centipawns(&self, evaluation: f32) -> f32 {
+        evaluation * 100.0 / self.pawn_value()
+    }
+    pub fn material(&self, board: &Board) -> f32 {
+        // the middlegame piece values alone, from white's point of view
+        let mut material = 0;
+        for tile in board.tiles.iter().flatten() {
+            if let Some(piece) = tile.present_piece {
+                if piece.ptype != PieceType::King {
+                    let value = self.material_mg[piece.ptype.as_int() as usize - 1];
+                    material += if piece.color == Team::White { value } else { -value };
+                }
+            }
+        }
+        return material as f32;
+    }
+    pub fn evaluate(&self, board: &Board) -> f32 {
+        // from white's point of view
+        let (mut mg, mut eg, mut fixed, mut phase) = (0, 0, 0, 0);
+        let mut bishops = [0, 0];
+        for (row, tiles) in board.tiles.iter().enumerate() {
+            for (col, tile) in tiles.iter().enumerate() {
+                let piece = match tile.present_piece {
+                    Some(piece) => piece,
+                    None => continue
+                };
+                let p = piece.ptype.as_int() as usize - 1;
+                let (sign, rank_row) = if piece.color == Team::White { (1, row) } else { (-1, 7 - row) };
+                mg += sign * self.psqt_mg[p][rank_row][col];
+                eg += sign * self.psqt_eg[p][rank_row][col];
+                if piece.ptype != PieceType::King {
+                    mg += sign * self.material_mg[p];
+                    eg += sign * self.material_eg[p];
+                    phase += PHASE_WEIGHTS[p];
+                }
+                if piece.ptype == PieceType::Bishop {
+                    bishops[(sign < 0) as usize] += 1;
+                }
+            }
+        }
+        for (i, sign) in [1, -1].iter().enumerate() {
+            if bishops[i] >= 2 {
+                fixed += sign * self.bishop_pair;
+            }
+        }
+        let phase = phase.min(MAX_PHASE);
+        return fixed as f32 + (mg * phase + eg * (MAX_PHASE - phase)) as f32 / MAX_PHASE as f32;
+    }
+}
+
+
+pub fn eval_coefficients(board: &Board) -> Vec<(usize, f32)> {
+    // evaluate is linear in the parameters, so a position is the weight each parameter gets in it
+    let mut coefficients: Vec<(usize, f32)> = Vec::new();
+    let mut add = |idx: usize, value: f32| {
+        match coefficients.iter_mut().find(|(other, _)| *other == idx) {
+            Some(entry) => entry.1 += value,
+            None => coefficients.push((idx, value))
+        }
+    };
+    let mut phase = 0;
+    let mut bishops = [0, 0];
+    // middlegame and endgame parameter of each term
+    let mut tapered = Vec::new();
+    for (row, tiles) in board.tiles.iter().enumerate() {
+        for (col, tile) in tiles.iter().enumerate() {
+            let piece = match tile.present_piece {
+                Some(piece) => piece,
+                None => continue
+            };
+            let p = piece.ptype.as_int() as usize - 1;
+            let (sign, rank_row) = if piece.color == Team::White { (1.0, row) } else { (-1.0, 7 - row) };
+            tapered.push((PSQT_MG_OFFSET + p * 64 + rank_row * 8 + col, PSQT_EG_OFFSET + p * 64 + rank_row * 8 + col, sign));
+            if piece.ptype != PieceType::King {
+                tapered.push((p, 5 + p, sign));
+                phase += PHASE_WEIGHTS[p];
+            }
+            if piece.ptype == PieceType::Bishop {
+                bishops[(sign < 0.0) as usize] += 1;
+            }
+        }
+    }
+    let mg_share = phase.min(MAX_PHASE) as f32 / MAX_PHASE as f32;
+    for (mg_idx, eg_idx, sign) in tapered.into_iter() {
+        add(mg_idx, sign * mg_share);
+        add(eg_idx, sign * (1.0 - mg_share));
+    }
+    for (i, sign) in [1.0, -1.0].iter().enumerate() {
+        if bishops[i] >= 2 {
+            add(BISHOP_PAIR_IDX, *sign);
+        }
+    }
+    coefficients.retain(|(_, value)| *value != 0.0);
+    return coefficients;
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POSITIONS: [&str; 4] = [
+        "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+        "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N2N2/PP2BPPP/R2QKB1R w KQ - 0 9",
+        "8/5pk1/6p1/3B4/8/6P1/5PK1/8 b - - 0 40",
+        "4k3/8/8/8/3K4/8/4P3/8 w - - 0 60"
+    ];
+
+    #[test]
+    fn coefficients_reproduce_evaluate() {
+        let mut params = EvalParams::default();
+        params.bishop_pair = 30;
+        let weights = params.to_vector();
+        for fen in POSITIONS.iter() {
+            let (board, _) = Board::from_fen(fen).unwrap();
+            let linear: f32 = eval_coefficients(&board).iter().map(|(idx, value)| weights[*idx] as f32 * value).sum();
+            assert!((linear - params.evaluate(&board)).abs() < 0.01, "{}: {} vs {}", fen, linear, params.evaluate(&board));
+        }
+    }
+
+    #[test]
+    fn vector_round_trips() {
+        let mut params = EvalParams::default();
+        params.material_eg[3] = 1400;
+        params.psqt_eg[5][3][4] = 17;
+        params.bishop_pair = -12;
+        assert!(EvalParams::from_vector(&params.to_vector()) == params);
+        assert_eq!(params.to_vector().len(), N_PARAMS);
+    }
+
+    #[test]
+    fn start_position_is_level() {
+        let (board, _) = Board::from_fen(POSITIONS[0]).unwrap();
+        assert_eq!(EvalParams::default().evaluate(&board), 0.0);
+        assert_eq!(EvalParams::default().material(&board), 0.0);
+    }
+
+    #[test]
+    fn endgame_king_prefers_the_centre() {
+        let params = EvalParams::default();
+        let (centre, _) = Board::from_fen("4k3/8/8/8/3K4/8/4P3/8 w - - 0 60").unwrap();
+        let (corner, _) = Board::from_fen("4k3/8/8/8/8/8/4P3/6K1 w - - 0 60").unwrap();
+        assert!(params.evaluate(&centre) > params.evaluate(&corner));
+    }
+
+    #[test]
+    fn converts_to_centipawns() {
+        let params = EvalParams::default();
+        assert_eq!(params.to_centipawns(params.pawn_value()), 100.0);
+        assert_eq!(params.to_centipawns(-2.0 * params.pawn_value()), -200.0);
+    }
+}
@@ -1,9 +1,95 @@
 use macroquad::prelude::*;
 use macroquad::audio::*;
+use std::fs;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use serde::{Serialize, Deserialize};
+#[path = "settings.rs"] mod settings;
+pub use settings::*;
 #[path = "config.rs"] mod config;
-use config::Config;
+pub use config::*;
 #[path = "algo.rs"] mod algo;
 pub use algo::*;
+#[path = "movelist.rs"] mod movelist;
+use movelist::MoveList;
+#[path = "annotations.rs"] mod annotations;
+pub use annotations::*;
+#[path = "pgn.rs"] mod pgn;
+pub use pgn::*;
+#[path = "animation.rs"] mod animation;
+use animation::{Animation, piece_positions};
+#[path = "savegame.rs"] mod savegame;
+pub use savegame::*;
+#[path = "review.rs"] mod review;
+pub use review::*;
+
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum EndReason {
+    Checkmate,
+    Stalemate,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    Resignation,
+    AgreedDraw,
+    Timeout,
+    TimeoutVsInsufficientMaterial
+}
+
+impl EndReason {
+    pub fn as_string(&self) -> String {
+        match *self {
+            Self::Checkmate => "Checkmate".to_string(),
+            Self::Stalemate => "Stalemate".to_string(),
+            Self::Repetition => "Threefold Repetition".to_string(),
+            Self::FiftyMoves => "Fifty-Move Rule".to_string(),
+            Self::InsufficientMaterial => "Insufficient Material".to_string(),
+            Self::Resignation => "Resignation".to_string(),
+            Self::AgreedDraw => "Draw Agreed".to_string(),
+            Self::Timeout => "Time Out".to_string(),
+            Self::TimeoutVsInsufficientMaterial => "Time Out vs Insufficient Material".to_string()
+        }
+    }
+}
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ResultAction {
+    Rematch,
+    Analyse,
+    SavePgn,
+    MainMenu
+}
+
+impl ResultAction {
+    pub fn all() -> [ResultAction; 4] {
+        [Self::Rematch, Self::Analyse, Self::SavePgn, Self::MainMenu]
+    }
+    pub fn as_string(&self) -> String {
+        match *self {
+            Self::Rematch => "Rematch".to_string(),
+            Self::Analyse => "Analyse game".to_string(),
+            Self::SavePgn => "Save PGN".to_string(),
+            Self::MainMenu => "Main menu".to_string()
+        }
+    }
+}
+
+
+pub struct Snapshot {
+    pub board: Board,
+    pub next_player: Team,
+    pub winner: Team,
+    pub game_over: bool,
+    pub end_reason: Option<EndReason>,
+    pub halfmove_clock: usize,
+    pub key: u64,
+    pub san: String,
+    pub nags: Vec<u8>,
+    pub annotations: Annotations,
+    pub comment: String
+}
 
 
 pub struct Game {
@@ -15,81 +101,820 @@ pub struct Game {
     pub config: Config,
     pub winner: Team,
     pub game_over: bool,
+    pub end_reason: Option<EndReason>,
+    pub halfmove_clock: usize,
+    pub start_fen: Option<String>,
+    start_ply: usize,
+    pub headers: Vec<String>,
     pub win_screen_timer: Timer,
+    pub show_result: bool,
+    pub result_message: String,
+    pub wants_menu: bool,
+    pub draw_offer: Option<Team>,
+    pub ai_eval: Option<f32>,
+    ai_losing_moves: usize,
+    confirm_resign: bool,
+    pub notice: String,
+    pub notice_timer: Timer,
+    pub time_control: Option<TimeControl>,
+    pub clocks: [Timer; 2],
+    turn_start: u128,
+    low_time_warned: [bool; 2],
     pub use_ai: bool,
+    pub selected: Option<(isize, isize)>,
+    pub premove: Option<Move>,
+    pub annotation_start: Option<(isize, isize)>,
+    pub animation: Option<Animation>,
+    pub human_side: Team,
+    pub flipped: bool,
+    pub analysis_mode: bool,
+    pub analysis: Option<BackgroundSearch>,
+    analysis_key: u64,
+    // one per side to move, so stepping through the game keeps each transposition table
+    analysis_engines: [Algorithms; 2],
+    review: Option<JoinHandle<Result<(PgnGame, GameReview), String>>>,
+    pub use_ponder: bool,
+    // the depth of this game, a loaded game keeps its own without changing the settings
+    pub search_depth: isize,
+    pub search: Option<BackgroundSearch>,
+    pub ponder: Option<Ponder>,
+    pub show_stats: bool,
+    pub history: Vec<Snapshot>,
+    pub cursor: usize,
+    pub move_list: MoveList,
 }
 
 impl Game {
     pub async fn new() -> Self {
+        let config = Config::new().await;
+        let mut algorithms = Algorithms::new(Team::Black);
+        algorithms.multi_pv = config.settings.multi_pv.clamp(1, MAX_MULTI_PV);
         Self {
             next_player: Team::White,
             hovered_tile: (-1, -1),
             board: Board::new(),
-            algorithms: Algorithms::new(Team::Black),
+            algorithms: algorithms,
             dragger: Dragger::new(),
-            config: Config::new().await,
+            use_ponder: config.settings.ponder,
+            search_depth: config.settings.search_depth,
+            config: config,
             winner: Team::None,
             game_over: false,
+            end_reason: None,
+            halfmove_clock: 0,
+            start_fen: None,
+            start_ply: 0,
+            headers: Vec::new(),
             win_screen_timer: Timer::new(6000),
+            show_result: false,
+            result_message: String::new(),
+            wants_menu: false,
+            draw_offer: None,
+            ai_eval: None,
+            ai_losing_moves: 0,
+            confirm_resign: false,
+            notice: String::new(),
+            notice_timer: Timer::new(NOTICE_TIME),
+            time_control: None,
+            clocks: [Timer::new(0), Timer::new(0)],
+            turn_start: 0,
+            low_time_warned: [false, false],
             use_ai: false,
+            selected: None,
+            premove: None,
+            annotation_start: None,
+            animation: None,
+            human_side: Team::White,
+            flipped: false,
+            analysis_mode: false,
+            analysis: None,
+            analysis_key: 0,
+            analysis_engines: [Algorithms::new(Team::Black), Algorithms::new(Team::White)],
+            review: None,
+            search: None,
+            ponder: None,
+            show_stats: false,
+            history: Vec::new(),
+            cursor: 0,
+            move_list: MoveList::new(),
         }
     }
     pub fn init(&mut self) {
         self.board.calc_team_valid_moves(self.next_player);
+        self.history = Vec::from([self.snapshot(String::new())]);
+        self.cursor = 0;
+        if let Some(tc) = self.time_control {
+            self.clocks = [Timer::new(tc.base), Timer::new(tc.base)];
+            self.clocks[self.next_player.as_int() as usize].activate();
+            self.turn_start = 0;
+        }
     }
-    pub async fn compute_move(&mut self, tilesize: f32) {
-        if self.winner == Team::None {
-            self.algorithms.evaluated_states = 0;
-            self.algorithms.pruned_states = 0;
-            self.algorithms.transpositions = 0;
-            self.draw(tilesize).await;
-            next_frame().await;
-
-            let (evaluation, (piece, action)) = self.algorithms.search_multi(self.board.copy(), 4);
-            // if self.algorithms.nth_move > 10 {
-            //     (evaluation, (piece, action)) = self.algorithms.alphabetatrans(self.board, 4);
-            // }
-            // else {
-            //     (evaluation, (piece, action)) = self.algorithms.choose_from_book(self.board);
-            // }
-            if piece.is_some() {
-                self.execute_move(&mut piece.unwrap(), action.unwrap());
-                self.algorithms.nth_move += 1;
-            }
-        }
-    }
-    pub fn execute_move(&mut self, mut piece: &mut Piece, action: Move) {
-        if piece.ptype != PieceType::None {
-            let captured = self.board.tiles[action.end.row as usize][action.end.col as usize].has_piece();
-            self.board.execute_move(piece, action.copy(), false, false);
+    pub fn start_position(&mut self, fen: Option<&str>) -> Result<(), String> {
+        let (board, next_player) = match fen {
+            Some(fen) => Board::from_fen(fen)?,
+            None => (Board::new(), Team::White)
+        };
+        self.cancel_search();
+        self.board = board;
+        self.next_player = next_player;
+        self.winner = Team::None;
+        self.game_over = false;
+        self.end_reason = None;
+        self.show_result = false;
+        let (halfmove, fullmove) = fen.map_or((0, 1), fen_counters);
+        self.halfmove_clock = halfmove;
+        self.start_ply = (fullmove - 1) * 2 + (next_player == Team::Black) as usize;
+        self.start_fen = fen.map(|fen| fen.trim().to_string());
+        self.move_list.offset = self.start_ply;
+        self.headers.clear();
+        self.init();
+        return Ok(());
+    }
+    pub fn snapshot(&self, san: String) -> Snapshot {
+        Snapshot {
+            board: self.board.copy(),
+            next_player: self.next_player,
+            winner: self.winner,
+            game_over: self.game_over,
+            end_reason: self.end_reason,
+            halfmove_clock: self.halfmove_clock,
+            key: TranspositionTable::repetition_key(&self.board, self.next_player),
+            san: san,
+            nags: Vec::new(),
+            annotations: Annotations::default(),
+            comment: String::new()
+        }
+    }
+    pub fn is_live(&self) -> bool {
+        self.cursor + 1 >= self.history.len()
+    }
+    pub fn goto(&mut self, ply: usize) {
+        // the result stays with the game, looking at an earlier position does not reopen it
+        self.jump(ply, true);
+    }
+    fn jump(&mut self, ply: usize, keep_result: bool) {
+        if ply >= self.history.len() {
+            return;
+        }
+        let before = piece_positions(&self.board);
+        let snapshot = &self.history[ply];
+        self.board = snapshot.board.copy();
+        self.next_player = snapshot.next_player;
+        self.halfmove_clock = snapshot.halfmove_clock;
+        let result = if keep_result { self.history.last().unwrap() } else { &self.history[ply] };
+        self.winner = result.winner;
+        self.game_over = result.game_over;
+        self.end_reason = result.end_reason;
+        self.cursor = ply;
+        self.board.calc_team_valid_moves(self.next_player);
+        self.dragger.end_drag();
+        self.selected = None;
+        self.premove = None;
+        self.win_screen_timer.reset();
+        self.show_result = false;
+        self.move_list.follow(ply);
+        self.animate_from(&before, None);
+    }
+    pub fn animate_from(&mut self, before: &[(Piece, (isize, isize))], skip: Option<isize>) {
+        self.animation = match self.config.settings.animation_ms {
+            0 => None,
+            ms => Animation::between(before, &self.board, skip, ms as u128)
+        };
+    }
+    pub fn update_animation(&mut self) {
+        if let Some(animation) = &mut self.animation {
+            animation.timer.update();
+            if animation.timer.finished {
+                self.animation = None;
+            }
+        }
+    }
+    pub fn is_animating(&self) -> bool {
+        self.animation.is_some()
+    }
+    pub fn step_history(&mut self, delta: isize) {
+        let ply = (self.cursor as isize + delta).clamp(0, self.history.len() as isize - 1);
+        self.goto(ply as usize);
+    }
+    pub fn end_game(&mut self, winner: Team, reason: EndReason) {
+        self.winner = winner;
+        self.game_over = true;
+        self.end_reason = Some(reason);
+        self.show_result = true;
+        self.result_message.clear();
+        self.win_screen_timer.reset();
+        for clock in self.clocks.iter_mut() {
+            clock.pause();
+        }
+    }
+    pub fn update_clocks(&mut self) {
+        // browsing the game leaves the clock of the side to move in the live position running
+        let side = match self.history.last() {
+            Some(live) if !live.game_over => live.next_player,
+            _ => return
+        };
+        if self.time_control.is_none() {
+            return;
+        }
+        let idx = side.as_int() as usize;
+        self.clocks[idx].update();
+        let human = !self.use_ai || (side == self.human_side);
+        if human && !self.low_time_warned[idx] && (self.clocks[idx].remaining() < LOW_TIME) {
+            self.low_time_warned[idx] = true;
+            self.playsound(SoundEvent::LowTime);
+        }
+        if self.clocks[idx].finished {
+            self.playsound(SoundEvent::GameEnd);
+            self.cancel_search();
+            if self.history.last().unwrap().board.has_mating_material(side.other()) {
+                self.end_game(side.other(), EndReason::Timeout);
+            }
+            else {
+                self.end_game(Team::None, EndReason::TimeoutVsInsufficientMaterial);
+            }
+            self.record_result();
+        }
+    }
+    fn record_result(&mut self) {
+        // results decided between moves belong to the position they happened in
+        if let Some(last) = self.history.last_mut() {
+            last.winner = self.winner;
+            last.game_over = true;
+            last.end_reason = self.end_reason;
+        }
+        self.autosave();
+    }
+    pub fn show_notice(&mut self, text: &str) {
+        self.notice = text.to_string();
+        self.notice_timer.activate();
+    }
+    pub fn update_notice(&mut self) {
+        self.notice_timer.update();
+        if !self.notice_timer.active {
+            self.notice.clear();
+            self.confirm_resign = false;
+        }
+    }
+    pub fn resign(&mut self) {
+        if self.game_over {
+            return;
+        }
+        // a stray key press should not throw the game away
+        if !self.confirm_resign {
+            self.show_notice("Press X again to resign");
+            self.confirm_resign = true;
+            return;
+        }
+        self.confirm_resign = false;
+        self.notice.clear();
+        self.goto(self.history.len() - 1);
+        let loser = self.controlled_side();
+        self.cancel_search();
+        self.end_game(loser.other(), EndReason::Resignation);
+        self.record_result();
+        self.playsound(SoundEvent::GameEnd);
+    }
+    pub fn offer_draw(&mut self) {
+        if self.game_over {
+            return;
+        }
+        let side = self.controlled_side();
+        if self.use_ai {
+            if self.ai_accepts_draw() {
+                self.agree_draw();
+            }
+            else {
+                self.show_notice("The computer declines the draw");
+            }
+        }
+        else if self.draw_offer == Some(side.other()) {
+            self.agree_draw();
+        }
+        else {
+            self.draw_offer = Some(side);
+            self.show_notice(&format!("{} offers a draw, D to accept", side.as_string()));
+        }
+    }
+    fn agree_draw(&mut self) {
+        self.draw_offer = None;
+        self.goto(self.history.len() - 1);
+        self.cancel_search();
+        self.end_game(Team::None, EndReason::AgreedDraw);
+        self.record_result();
+        self.playsound(SoundEvent::GameEnd);
+    }
+    fn ai_accepts_draw(&self) -> bool {
+        // the ai takes a draw when it is not better, both by search and by the material count
+        let pawn = self.algorithms.eval.pawn_value();
+        let ai_side = self.human_side.other();
+        let sign = if ai_side == Team::White { 1.0 } else { -1.0 };
+        let material = self.algorithms.eval.material(&self.history.last().unwrap().board) * sign;
+        let evaluation = self.ai_eval.unwrap_or(material);
+        (evaluation <= DRAW_ACCEPT_MARGIN * pawn) && (material <= pawn)
+    }
+    fn ai_resigns(&mut self, evaluation: f32) -> bool {
+        let threshold = self.config.settings.resign_threshold;
+        if threshold <= 0.0 {
+            return false;
+        }
+        // only a position that stays lost for several moves is resigned
+        if evaluation < -threshold * self.algorithms.eval.pawn_value() {
+            self.ai_losing_moves += 1;
+        }
+        else {
+            self.ai_losing_moves = 0;
+        }
+        self.ai_losing_moves >= self.config.settings.resign_moves.max(1)
+    }
+    pub fn switch_clocks(&mut self, mover: Team) {
+        let tc = match self.time_control {
+            Some(tc) => tc,
+            None => return
+        };
+        let clock = &mut self.clocks[mover.as_int() as usize];
+        clock.pause();
+        // bronstein delay: give back the time spent on the move, up to the delay
+        let used = clock.runtime.saturating_sub(self.turn_start);
+        clock.duration += tc.increment + used.min(tc.delay);
+        if !self.game_over {
+            let next = &mut self.clocks[mover.other().as_int() as usize];
+            next.resume();
+            self.turn_start = next.runtime;
+        }
+    }
+    pub fn sync_clocks(&mut self) {
+        // hands the running clock to whoever is to move once the game is cut back to the shown position
+        if self.time_control.is_none() || self.game_over || self.clocks.iter().any(|clock| clock.finished) {
+            return;
+        }
+        for clock in self.clocks.iter_mut() {
+            clock.pause();
+        }
+        let next = &mut self.clocks[self.next_player.as_int() as usize];
+        next.resume();
+        self.turn_start = next.runtime;
+    }
+    pub fn update_search(&mut self) {
+        if self.game_over {
+            return;
+        }
+        match &self.search {
+            None => {
+                self.search = Some(match self.take_ponder_search() {
+                    Some(search) => search,
+                    None => BackgroundSearch::start(&self.algorithms, self.board.copy(), self.search_depth)
+                });
+            },
+            Some(search) if search.is_finished() && !self.is_animating() => {
+                // the reply is held back until the previous move has finished animating
+                let outcome = self.search.take().unwrap().wait();
+                let (evaluation, (piece, action)) = outcome.result;
+                self.algorithms.stats = outcome.stats;
+                self.algorithms.lines = outcome.lines;
+                self.ai_eval = Some(evaluation);
+                if self.ai_resigns(evaluation) {
+                    self.end_game(self.human_side, EndReason::Resignation);
+                    self.record_result();
+                    self.playsound(SoundEvent::GameEnd);
+                    return;
+                }
+                // if self.algorithms.nth_move > 10 {
+                //     (evaluation, (piece, action)) = self.algorithms.alphabetatrans(self.board, 4);
+                // }
+                // else {
+                //     (evaluation, (piece, action)) = self.algorithms.choose_from_book(self.board);
+                // }
+                if piece.is_some() {
+                    let root = self.board.copy();
+                    self.execute_move(&mut piece.unwrap(), action.clone().unwrap());
+                    self.algorithms.nth_move += 1;
+                    if self.use_ponder {
+                        self.start_ponder(&root, (piece.unwrap(), action.unwrap()));
+                    }
+                }
+            },
+            _ => ()
+        }
+    }
+    pub fn update_analysis(&mut self) {
+        let key = TranspositionTable::key(&self.board, self.next_player) ^ self.board.move_log.len() as u64;
+        if self.analysis.is_some() && (key == self.analysis_key) {
+            return;
+        }
+        if let Some(search) = self.analysis.take() {
+            search.cancel();
+        }
+        self.analysis_key = key;
+        // earlier positions of a finished game can still be analysed
+        if !self.history[self.cursor].game_over {
+            let engine = &mut self.analysis_engines[self.next_player.as_int() as usize];
+            engine.multi_pv = self.algorithms.multi_pv;
+            self.analysis = Some(BackgroundSearch::start(engine, self.board.copy(), ANALYSIS_DEPTH));
+        }
+    }
+    pub fn cancel_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            search.cancel();
+        }
+        if let Some(search) = self.analysis.take() {
+            search.cancel();
+        }
+        if let Some(ponder) = self.ponder.take() {
+            ponder.search.cancel();
+        }
+    }
+    pub fn start_ponder(&mut self, root: &Board, best: (Piece, Move)) {
+        let pv = self.algorithms.principal_variation(root, best, 2);
+        if (pv.len() < 2) || self.game_over {
+            return;
+        }
+        let (mut piece, reply) = pv[1].clone();
+        let mut state = self.board.copy();
+        state.calc_team_valid_moves(piece.color);
+        state.execute_move(&mut piece, reply.copy(), false, false);
+        state.set_en_passant(&mut piece, reply.copy());
+        self.ponder = Some(Ponder {
+            expected: reply,
+            search: BackgroundSearch::start(&self.algorithms, state, self.search_depth)
+        });
+    }
+    pub fn take_ponder_search(&mut self) -> Option<BackgroundSearch> {
+        let ponder = self.ponder.take()?;
+        let ponderhit = match self.board.move_log.last() {
+            Some(last) => last.0 == ponder.expected,
+            None => false
+        };
+        if ponderhit {
+            // the ponder search is already looking at this position, keep it running
+            return Some(ponder.search);
+        }
+        ponder.search.cancel();
+        return None;
+    }
+    pub fn execute_move(&mut self, piece: &mut Piece, action: Move) {
+        if (piece.ptype != PieceType::None) && !self.game_over {
+            if !self.is_live() {
+                // playing a move from an earlier position discards the moves after it
+                self.truncate_history();
+            }
+            let before = piece_positions(&self.board);
+            let castle = (piece.ptype == PieceType::King) && self.board.is_castling(action.initial.copy(), action.end.copy());
+            let promote = (piece.ptype == PieceType::Pawn) && ((action.end.row == 0) || (action.end.row == 7));
+            if self.use_ai && (self.next_player == self.human_side) {
+                // the ai's last score no longer describes the position
+                self.ai_eval = None;
+            }
+            // moving instead of accepting declines the opponent's offer
+            if self.draw_offer == Some(self.next_player.other()) {
+                self.draw_offer = None;
+            }
+            let captured = self.push_move(piece, action);
+            let event = if self.game_over {
+                SoundEvent::GameEnd
+            }
+            else if self.board.get_pins_and_checks(self.next_player).0 {
+                SoundEvent::Check
+            }
+            else if promote {
+                SoundEvent::Promote
+            }
+            else if castle {
+                SoundEvent::Castle
+            }
+            else if captured {
+                SoundEvent::Capture
+            }
+            else {
+                SoundEvent::Move
+            };
+            self.playsound(event);
+            // a dropped piece is already where it belongs
+            let dropped = if self.dragger.dragging { self.dragger.piece.map(|piece| piece.uid) } else { None };
+            self.animate_from(&before, dropped);
+            self.move_list.follow(self.cursor);
+            self.autosave();
+        }
+    }
+    fn push_move(&mut self, mut piece: &mut Piece, action: Move) -> bool {
+        let san = self.board.to_san(*piece, &action);
+        let captured = self.board.tiles[action.end.row as usize][action.end.col as usize].has_piece();
+        let irreversible = captured || (piece.ptype == PieceType::Pawn);
+        self.board.execute_move(piece, action.copy(), false, false);
 
-            // if self.board.in_checkmate(self.algorithms.opponent) {
-            //     self.winner = self.algorithms.perspective;
-            // }
-            self.board.set_en_passant(&mut piece, action.copy());
-            self.playsound(captured);
-            self.next_turn();
+        // if self.board.in_checkmate(self.algorithms.opponent) {
+        //     self.winner = self.algorithms.perspective;
+        // }
+        self.board.set_en_passant(&mut piece, action.copy());
+        self.halfmove_clock = if irreversible { 0 } else { self.halfmove_clock + 1 };
+        let mover = self.next_player;
+        self.next_turn();
+        self.check_draws();
+        self.switch_clocks(mover);
+        self.history.push(self.snapshot(san));
+        self.cursor = self.history.len() - 1;
+        return captured;
+    }
+    pub fn annotate(&mut self, from: (isize, isize), to: (isize, isize), color: MarkColor) {
+        let annotations = &mut self.history[self.cursor].annotations;
+        if from == to {
+            annotations.toggle_square(from, color);
+        }
+        else {
+            annotations.toggle_arrow(from, to, color);
         }
     }
+    pub fn result_text(&self) -> String {
+        if !self.game_over {
+            return "*".to_string();
+        }
+        match self.winner {
+            Team::White => "1-0".to_string(),
+            Team::Black => "0-1".to_string(),
+            Team::None => "1/2-1/2".to_string()
+        }
+    }
+    pub fn to_pgn(&self) -> PgnGame {
+        let mut pgn = PgnGame::new();
+        let (white, black) = match (self.use_ai, self.human_side) {
+            (true, Team::White) => ("Player", "Computer"),
+            (true, _) => ("Computer", "Player"),
+            (false, _) => ("Player", "Player")
+        };
+        pgn.set_tag("Event", "Casual game");
+        pgn.set_tag("Site", "?");
+        pgn.set_tag("White", white);
+        pgn.set_tag("Black", black);
+        pgn.set_tag("Result", &self.result_text());
+        if let Some(fen) = &self.start_fen {
+            pgn.set_tag("SetUp", "1");
+            pgn.set_tag("FEN", fen);
+        }
+        pgn.comment = join_comment(&self.history[0].annotations, &self.history[0].comment);
+        for snapshot in self.history.iter().skip(1) {
+            pgn.moves.push(PgnMove {
+                san: snapshot.san.clone(),
+                nags: snapshot.nags.clone(),
+                comment: join_comment(&snapshot.annotations, &snapshot.comment)
+            });
+        }
+        pgn.result = self.result_text();
+        return pgn;
+    }
+    pub fn load_pgn(&mut self, pgn: &PgnGame) -> Result<(), String> {
+        self.time_control = None;
+        self.start_position(pgn.tag("FEN"))?;
+        (self.history[0].annotations, self.history[0].comment) = Annotations::from_comment(&pgn.comment);
+        for (i, pgn_move) in pgn.moves.iter().enumerate() {
+            let (mut piece, action) = match self.board.find_san(self.next_player, &pgn_move.san) {
+                Some(found) => found,
+                None => return Err(format!("illegal move {}{} {}", i / 2 + 1, if i % 2 == 0 { "." } else { "..." }, pgn_move.san))
+            };
+            self.push_move(&mut piece, action);
+            let snapshot = self.history.last_mut().unwrap();
+            (snapshot.annotations, snapshot.comment) = Annotations::from_comment(&pgn_move.comment);
+            snapshot.nags = pgn_move.nags.clone();
+        }
+        self.goto(self.history.len() - 1);
+        return Ok(());
+    }
+    pub fn view_pgn(&mut self, pgn: &PgnGame) -> Result<(), String> {
+        // browsing a collection game starts at the first position with its headers shown
+        self.use_ai = false;
+        self.analysis_mode = false;
+        self.set_human_side(Team::White);
+        self.load_pgn(pgn)?;
+        self.headers = pgn_headers(pgn);
+        self.goto(0);
+        return Ok(());
+    }
+    pub fn save_pgn(&self, path: &str) -> Result<(), String> {
+        if let Some(dir) = Path::new(path).parent() {
+            fs::create_dir_all(dir).map_err(|err| format!("could not create {}: {}", dir.display(), err))?;
+        }
+        fs::write(path, self.to_pgn().to_text()).map_err(|err| format!("could not write {}: {}", path, err))
+    }
+    pub fn open_pgn(&mut self, path: &str) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|err| format!("could not read {}: {}", path, err))?;
+        match parse_pgn(&text).first() {
+            Some(pgn) => self.load_pgn(pgn),
+            None => Err(format!("no game found in {}", path))
+        }
+    }
+    pub fn save_game(&self, path: &str) -> Result<(), String> {
+        self.save_pgn(path)?;
+        let time_control = self.time_control.map(|tc| [tc.base as u64, tc.increment as u64, tc.delay as u64]);
+        let meta = SaveMeta {
+            use_ai: self.use_ai,
+            human_side: self.human_side,
+            analysis_mode: self.analysis_mode,
+            search_depth: self.search_depth,
+            multi_pv: self.algorithms.multi_pv,
+            time_control: time_control,
+            clocks: [self.clocks[0].remaining() as u64, self.clocks[1].remaining() as u64],
+            cursor: self.cursor,
+            winner: self.winner,
+            end_reason: self.end_reason
+        };
+        meta.save(path)
+    }
+    pub fn load_game(&mut self, path: &str) -> Result<(), String> {
+        self.open_pgn(path)?;
+        // a plain pgn without metadata opens as a two player game at its last move
+        let meta = match SaveMeta::load(path) {
+            Some(meta) => meta,
+            None => return Ok(())
+        };
+        self.use_ai = meta.use_ai;
+        self.set_human_side(meta.human_side);
+        self.analysis_mode = meta.analysis_mode;
+        self.search_depth = meta.search_depth;
+        self.algorithms.multi_pv = meta.multi_pv.clamp(1, MAX_MULTI_PV);
+        if let Some([base, increment, delay]) = meta.time_control {
+            self.time_control = Some(TimeControl {base: base as u128, increment: increment as u128, delay: delay as u128});
+            self.clocks = [Timer::new(meta.clocks[0] as u128), Timer::new(meta.clocks[1] as u128)];
+            self.turn_start = 0;
+            if !self.game_over {
+                self.clocks[self.next_player.as_int() as usize].activate();
+            }
+        }
+        if let (Some(reason), false) = (meta.end_reason, self.game_over) {
+            // resignations, agreed draws and flag falls are not visible in the moves
+            self.end_game(meta.winner, reason);
+            self.record_result();
+        }
+        if meta.cursor + 1 < self.history.len() {
+            self.goto(meta.cursor);
+        }
+        self.show_result = self.game_over && self.is_live();
+        return Ok(());
+    }
+    pub fn autosave(&self) {
+        // an empty board would overwrite the last game worth continuing
+        if self.history.len() < 2 {
+            return;
+        }
+        if let Err(err) = self.save_game(AUTOSAVE_PGN) {
+            eprintln!("{}", err);
+        }
+    }
+    pub fn start_review(&mut self) {
+        if self.review.is_some() || (self.history.len() < 2) {
+            return;
+        }
+        let pgn = self.to_pgn();
+        let depth = self.search_depth;
+        self.review = Some(thread::spawn(move || review_game(&pgn, depth)));
+        self.show_notice("Reviewing the game...");
+    }
+    pub fn update_review(&mut self) {
+        if !self.review.as_ref().map_or(false, |handle| handle.is_finished()) {
+            return;
+        }
+        let (annotated, review) = match self.review.take().unwrap().join() {
+            Ok(Ok(result)) => result,
+            Ok(Err(err)) => {
+                self.show_notice(&format!("Review failed: {}", err));
+                return;
+            },
+            Err(_) => {
+                self.show_notice("Review failed");
+                return;
+            }
+        };
+        // moves played or taken back during the review keep their own annotations
+        (self.history[0].annotations, self.history[0].comment) = Annotations::from_comment(&annotated.comment);
+        for (snapshot, pgn_move) in self.history.iter_mut().skip(1).zip(annotated.moves.iter()) {
+            if snapshot.san != pgn_move.san {
+                break;
+            }
+            (snapshot.annotations, snapshot.comment) = Annotations::from_comment(&pgn_move.comment);
+            snapshot.nags = pgn_move.nags.clone();
+        }
+        if let Err(err) = self.save_pgn(REVIEWED_PGN) {
+            eprintln!("{}", err);
+        }
+        self.show_notice(&format!("Accuracy: White {:.0}%, Black {:.0}%", review.accuracy(Team::White), review.accuracy(Team::Black)));
+    }
+    fn truncate_history(&mut self) {
+        self.cancel_search();
+        self.history.truncate(self.cursor + 1);
+        // the ai's last search and losing streak belonged to the moves just discarded
+        self.ai_eval = None;
+        self.ai_losing_moves = 0;
+        self.sync_clocks();
+    }
+    fn is_rest_point(&self, ply: usize) -> bool {
+        // against the ai, undo and redo stop only where the human is to move
+        !self.use_ai || (self.history[ply].next_player == self.human_side) || (ply + 1 == self.history.len())
+    }
     pub fn undo_move(&mut self) {
-        let success = self.board.undo_last_move();
-        if success {
-            self.next_turn();
+        if self.cursor == 0 {
+            return;
+        }
+        self.cancel_search();
+        let mut ply = self.cursor - 1;
+        while (ply > 0) && !self.is_rest_point(ply) {
+            ply -= 1;
+        }
+        // taking moves back reopens a finished game
+        self.jump(ply, false);
+        if self.use_ai && (self.history[ply].next_player != self.human_side) {
+            // only reachable at the start when the ai plays white, let it move again
+            self.truncate_history();
+        }
+    }
+    pub fn redo_move(&mut self) {
+        if self.is_live() {
+            return;
+        }
+        let mut ply = self.cursor + 1;
+        while !self.is_rest_point(ply) {
+            ply += 1;
         }
+        self.jump(ply, false);
     }
     pub fn next_turn(&mut self) {
         self.next_player = self.next_player.other();
-        if self.next_player == self.algorithms.opponent {
-            self.board.calc_team_valid_moves(self.algorithms.opponent);
-            if self.board.in_checkmate(self.algorithms.opponent) {
-                self.winner = self.algorithms.perspective;
+        self.board.calc_team_valid_moves(self.next_player);
+        // no legal moves is mate when in check and stalemate otherwise
+        if self.board.in_checkmate(self.next_player) {
+            if self.board.get_pins_and_checks(self.next_player).0 {
+                self.end_game(self.next_player.other(), EndReason::Checkmate);
             }
+            else {
+                self.end_game(Team::None, EndReason::Stalemate);
+            }
+        }
+    }
+    fn check_draws(&mut self) {
+        if self.game_over {
+            return;
+        }
+        // the current position is not in the history yet, so two earlier matches make three
+        let key = TranspositionTable::repetition_key(&self.board, self.next_player);
+        let repeats = self.history.iter().filter(|snapshot| snapshot.key == key).count();
+        if repeats >= 2 {
+            self.end_game(Team::None, EndReason::Repetition);
+        }
+        else if self.halfmove_clock >= 100 {
+            self.end_game(Team::None, EndReason::FiftyMoves);
+        }
+        else if self.board.is_insufficient_material() {
+            self.end_game(Team::None, EndReason::InsufficientMaterial);
         }
-        if !self.use_ai && (self.next_player == self.algorithms.perspective) {
-            self.board.calc_team_valid_moves(self.next_player);
-            if self.board.in_checkmate(self.next_player) {
-                self.winner = self.next_player.other();
+    }
+    pub async fn apply_settings(&mut self, settings: Settings) {
+        self.use_ponder = settings.ponder;
+        self.search_depth = settings.search_depth;
+        self.algorithms.multi_pv = settings.multi_pv.clamp(1, MAX_MULTI_PV);
+        self.config.apply_settings(settings).await;
+    }
+    pub fn set_human_side(&mut self, side: Team) {
+        self.human_side = side;
+        self.algorithms.set_perspective(side.other());
+        self.flipped = side == Team::Black;
+    }
+    pub fn controlled_side(&self) -> Team {
+        if self.use_ai { self.human_side } else { self.next_player }
+    }
+    pub fn is_premove_turn(&self) -> bool {
+        self.use_ai && (self.next_player != self.human_side) && !self.game_over
+    }
+    pub fn try_move(&mut self, from: (isize, isize), to: (isize, isize)) -> bool {
+        if !inrange(to.0) || !inrange(to.1) || (from == to) {
+            return false;
+        }
+        let mut piece = match self.board.tiles[from.0 as usize][from.1 as usize].present_piece {
+            Some(piece) => piece,
+            None => return false
+        };
+        let mut initial = Tile::new();
+        let mut final_ = Tile::new();
+        initial.init(from.0, from.1, None);
+        final_.init(to.0, to.1, None);
+        let action = Move::new(initial, final_);
+        if self.is_premove_turn() {
+            // only the human's own pieces can be premoved, and only from the live position
+            if !self.is_live() || (piece.color != self.human_side) {
+                return false;
             }
+            // checked against the position once the ai has replied
+            self.premove = Some(action);
+            return true;
+        }
+        if self.board.is_valid(piece, action.clone()) {
+            self.execute_move(&mut piece, action);
+            return true;
+        }
+        self.playsound(SoundEvent::Illegal);
+        return false;
+    }
+    pub fn play_premove(&mut self) {
+        if self.is_premove_turn() || !self.is_live() || self.is_animating() {
+            return;
+        }
+        if let Some(action) = self.premove.take() {
+            self.try_move((action.initial.row, action.initial.col), (action.end.row, action.end.col));
         }
     }
     pub fn set_hover(&mut self, row: isize, col: isize) {
@@ -97,17 +922,28 @@ impl Game {
             self.hovered_tile = (row, col);
         }
     }
-    pub fn playsound(&mut self, capture: bool) {
-        if capture {
-            play_sound_once(self.config.capture_sound);
+    pub fn playsound(&mut self, event: SoundEvent) {
+        if !self.config.settings.sound {
+            return;
         }
-        else {
-            play_sound_once(self.config.move_sound);
+        if let Some(sound) = self.config.sound(event) {
+            play_sound(sound, PlaySoundParams{looped: false, volume: self.config.settings.volume});
         }
     }
     pub async fn reset(&mut self) {
+        self.cancel_search();
+        let (use_ai, analysis_mode, time_control, human_side, search_depth) = (self.use_ai, self.analysis_mode, self.time_control, self.human_side, self.search_depth);
+        let start_fen = self.start_fen.take();
         *self = Self::new().await;
-        self.init();
+        self.use_ai = use_ai;
+        self.set_human_side(human_side);
+        self.analysis_mode = analysis_mode;
+        self.time_control = time_control;
+        self.search_depth = search_depth;
+        // a rematch from a set up position starts from that position again
+        if self.start_position(start_fen.as_deref()).is_err() {
+            self.start_position(None).unwrap();
+        }
     }
     pub fn render_bg(&mut self, tilesize: f32) {
         for row in 0..ROWS {
@@ -120,6 +956,11 @@ impl Game {
                     color = self.config.theme.bg.dark;
                 }
                 draw_rectangle(col as f32 * tilesize, row as f32 * tilesize, tilesize, tilesize, color);
+                // labels follow the orientation, the square colours are symmetric under a flip
+                let (rank, file) = if self.flipped { (row + 1, COLS - 1 - col) } else { (ROWS - row, col) };
+                if !self.config.settings.coordinates {
+                    continue;
+                }
                 if col == 0 {
                     let text_color;
                     if (row % 2) == 0 {
@@ -129,7 +970,7 @@ impl Game {
                         text_color = self.config.theme.bg.light;
                     }
                     draw_text_ex(
-                        &format!("{}", ROWS - row),
+                        &format!("{}", rank),
                         5.0,
                         20.0 + row as f32 * tilesize,
                         TextParams{font: self.config.font, font_size: 18u16, color: text_color, ..Default::default()}
@@ -143,7 +984,7 @@ impl Game {
                     else {
                         text_color = self.config.theme.bg.light;
                     }
-                    let txt = match col {
+                    let txt = match file {
                         0 => "a",
                         1 => "b",
                         2 => "c",
@@ -163,34 +1004,106 @@ impl Game {
             }
         }
     }
-    pub async fn render_pieces(&mut self, tilesize: f32) {
+    pub fn render_pieces(&mut self, tilesize: f32) {
         for row in 0..ROWS {
             for col in 0..COLS {
                 if self.board.tiles[row][col].has_piece() {
-                    let piece = self.board.tiles[row][col].present_piece;
-                    if self.dragger.piece.is_none() || (piece.unwrap() != *self.dragger.piece.as_ref().unwrap()) {
-                        let im = load_texture(&piece.unwrap().texture_path(80)).await.unwrap();
-                        let pos = (col as f32 * tilesize, row as f32 * tilesize);
-                        draw_texture(im, pos.0 + tilesize / 2.0 - im.width() / 2.0, pos.1 + tilesize / 2.0 - im.height() / 2.0, Color::new(1.0, 1.0, 1.0, 1.0));
+                    let piece = self.board.tiles[row][col].present_piece.unwrap();
+                    let animated = self.animation.as_ref().map_or(false, |animation| animation.hides(&piece));
+                    if (self.dragger.piece.is_none() || (piece != *self.dragger.piece.as_ref().unwrap())) && !animated {
+                        let pos = tile_to_screen(row as isize, col as isize, tilesize, self.flipped);
+                        self.render_piece(&piece, pos, tilesize, 1.0);
                     }
                 }
             }
         }
     }
+    pub fn render_piece(&self, piece: &Piece, pos: (f32, f32), tilesize: f32, alpha: f32) {
+        if let Some(texture) = self.config.pieces.get(piece.color, piece.ptype, tilesize) {
+            draw_texture_ex(texture, pos.0, pos.1, Color::new(1.0, 1.0, 1.0, alpha), DrawTextureParams{dest_size: Some(vec2(tilesize, tilesize)), ..Default::default()});
+        }
+    }
+    pub fn render_animation(&self, tilesize: f32) {
+        let animation = match &self.animation {
+            Some(animation) => animation,
+            None => return
+        };
+        let t = animation.progress();
+        // captured pieces fade out under the arriving piece, restored ones fade back in
+        for (piece, square) in animation.captures.iter() {
+            self.render_piece(piece, tile_to_screen(square.0, square.1, tilesize, self.flipped), tilesize, 1.0 - t);
+        }
+        for (piece, square) in animation.appearing.iter() {
+            self.render_piece(piece, tile_to_screen(square.0, square.1, tilesize, self.flipped), tilesize, t);
+        }
+        for (piece, from, to) in animation.moves.iter() {
+            let start = tile_to_screen(from.0, from.1, tilesize, self.flipped);
+            let end = tile_to_screen(to.0, to.1, tilesize, self.flipped);
+            let pos = (start.0 + (end.0 - start.0) * t, start.1 + (end.1 - start.1) * t);
+            self.render_piece(piece, pos, tilesize, 1.0);
+        }
+    }
+    pub fn render_dragged(&self, tilesize: f32) {
+        if let Some(piece) = &self.dragger.piece {
+            // the dragged piece is drawn slightly larger, centred on the cursor
+            let size = tilesize * 1.2;
+            self.render_piece(piece, (self.dragger.mousex - size / 2.0, self.dragger.mousey - size / 2.0), size, 1.0);
+        }
+    }
+    pub fn render_check(&mut self, tilesize: f32) {
+        if !self.board.get_pins_and_checks(self.next_player).0 {
+            return;
+        }
+        if let Some((row, col)) = self.board.king_square(self.next_player) {
+            let (x, y) = tile_to_screen(row, col, tilesize, self.flipped);
+            draw_rectangle(x, y, tilesize, tilesize, Color::from_rgba(220, 40, 40, 190));
+        }
+    }
     pub fn render_valid_moves(&mut self, tilesize: f32) {
-        if self.dragger.dragging {
-            if !self.board.valid_moves.contains_key(self.dragger.piece.as_ref().unwrap()) {
-                return;
+        let (row, col) = match self.selected {
+            Some(tile) => tile,
+            None => return
+        };
+        let (x, y) = tile_to_screen(row, col, tilesize, self.flipped);
+        draw_rectangle(x, y, tilesize, tilesize, self.config.theme.trace.light);
+        if self.is_premove_turn() {
+            // the legal moves are only known once it is our turn again
+            return;
+        }
+        let piece = match self.board.tiles[row as usize][col as usize].present_piece {
+            Some(piece) => piece,
+            None => return
+        };
+        if !self.board.valid_moves.contains_key(&piece) {
+            return;
+        }
+        for action in self.board.valid_moves.get(&piece).unwrap() {
+            let color;
+            if ((action.end.row) + (action.end.col) % 2) == 0 {
+                color = self.config.theme.moves.light;
             }
-            for action in self.board.valid_moves.get(self.dragger.piece.as_ref().unwrap()).unwrap() {
-                let color;
-                if ((action.end.row) + (action.end.col) % 2) == 0 {
-                    color = self.config.theme.moves.light;
-                }
-                else {
-                    color = self.config.theme.moves.dark;
-                }
-                draw_rectangle(action.end.col as f32 * tilesize, action.end.row as f32 * tilesize, tilesize, tilesize, color);
+            else {
+                color = self.config.theme.moves.dark;
+            }
+            let pos = tile_to_screen(action.end.row, action.end.col, tilesize, self.flipped);
+            draw_rectangle(pos.0, pos.1, tilesize, tilesize, color);
+        }
+    }
+    pub fn render_annotations(&mut self, tilesize: f32) {
+        let annotations = self.history[self.cursor].annotations.clone();
+        for (square, color) in annotations.squares.iter() {
+            let (x, y) = tile_to_screen(square.0, square.1, tilesize, self.flipped);
+            draw_circle_lines(x + tilesize / 2.0, y + tilesize / 2.0, tilesize / 2.0 - 4.0, 5.0, color.color());
+        }
+        for (from, to, color) in annotations.arrows.iter() {
+            self.render_arrow(*from, *to, tilesize, color.color());
+        }
+    }
+    pub fn render_premove(&mut self, tilesize: f32) {
+        if let Some(action) = &self.premove {
+            for pos in [&action.initial, &action.end].iter() {
+                let (x, y) = tile_to_screen(pos.row, pos.col, tilesize, self.flipped);
+                draw_rectangle(x, y, tilesize, tilesize, Color::from_rgba(200, 60, 60, 140));
             }
         }
     }
@@ -206,63 +1119,407 @@ impl Game {
                 else {
                     color = self.config.theme.trace.dark;
                 }
-                draw_rectangle(pos.col as f32 * tilesize, pos.row as f32 * tilesize, tilesize, tilesize, color);
+                let (x, y) = tile_to_screen(pos.row, pos.col, tilesize, self.flipped);
+                draw_rectangle(x, y, tilesize, tilesize, color);
             }
         }
     }
     pub fn render_hover(&mut self, tilesize: f32) {
         if self.hovered_tile != (-1, -1) {
-            draw_rectangle_lines(self.hovered_tile.1 as f32 * tilesize, self.hovered_tile.0 as f32 * tilesize, tilesize, tilesize, 4.0, Color::from_rgba(180, 180, 180, 255))
+            let (x, y) = tile_to_screen(self.hovered_tile.0, self.hovered_tile.1, tilesize, self.flipped);
+            draw_rectangle_lines(x, y, tilesize, tilesize, 4.0, Color::from_rgba(180, 180, 180, 255))
         }
     }
-    pub fn render_win_screen(&mut self) {
-        let alpha = if self.win_screen_timer.runtime > 800 {
-            ((self.win_screen_timer.runtime as f32 / 1200.0) * 255.0) as u8
+    pub fn render_stats(&mut self, tilesize: f32) {
+        if self.show_stats {
+            for (i, line) in self.algorithms.stats.as_lines().iter().enumerate() {
+                draw_text_ex(
+                    line,
+                    self.panel_x(tilesize),
+                    self.panel_top() + 20.0 + i as f32 * 20.0,
+                    TextParams{font: self.config.font, font_size: 16u16, color: self.config.theme.title_color, ..Default::default()}
+                );
+            }
         }
-        else {
-            170
+    }
+    pub fn render_lines(&mut self, tilesize: f32) {
+        if self.algorithms.multi_pv < 2 {
+            return;
+        }
+        let lines = match (&self.search, &self.analysis) {
+            (Some(search), _) | (None, Some(search)) => search.progress().lines,
+            (None, None) => self.algorithms.lines.clone()
+        };
+        let y_offset = self.panel_top() + if self.show_stats { 180.0 } else { 20.0 };
+        for (i, line) in lines.iter().enumerate() {
+            let moves: Vec<String> = if self.analysis.is_some() {
+                // analysis lines always start from the displayed position
+                self.board.line_to_san(&line.moves[..line.moves.len().min(3)])
+            }
+            else {
+                line.moves.iter().take(3).map(|(_, m)| m.to_uci()).collect()
+            };
+            draw_text_ex(
+                &format!("{}. {} {}", i + 1, self.algorithms.eval.to_centipawns(line.evaluation) as i32, moves.join(" ")),
+                self.panel_x(tilesize),
+                y_offset + i as f32 * 20.0,
+                TextParams{font: self.config.font, font_size: 16u16, color: self.config.theme.title_color, ..Default::default()}
+            );
+        }
+    }
+    pub fn panel_top(&self) -> f32 {
+        let clocks = if self.time_control.is_some() { 45.0 } else { 0.0 };
+        let headers = if self.headers.is_empty() { 0.0 } else { self.headers.len() as f32 * 18.0 + 10.0 };
+        clocks + headers
+    }
+    pub fn render_headers(&mut self, tilesize: f32) {
+        let top = if self.time_control.is_some() { 45.0 } else { 0.0 };
+        let max_chars = ((screen_width() - self.panel_x(tilesize)) / 8.0).max(1.0) as usize;
+        for (i, line) in self.headers.iter().enumerate() {
+            let text: String = line.chars().take(max_chars).collect();
+            draw_text_ex(
+                &text,
+                self.panel_x(tilesize),
+                top + 18.0 + i as f32 * 18.0,
+                TextParams{font: self.config.font, font_size: 14u16, color: self.config.theme.title_color, ..Default::default()}
+            );
+        }
+    }
+    pub fn comment_lines(&self, tilesize: f32) -> Vec<String> {
+        // the comment on the displayed move, wrapped at word boundaries to the panel
+        let comment = &self.history[self.cursor].comment;
+        let max_chars = ((screen_width() - self.panel_x(tilesize)) / 8.0).max(8.0) as usize;
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        for word in comment.split_whitespace() {
+            if !line.is_empty() && (line.len() + word.len() + 1 > max_chars) {
+                lines.push(line);
+                line = String::new();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(word);
+        }
+        if !line.is_empty() {
+            lines.push(line);
+        }
+        lines.truncate(MAX_COMMENT_LINES);
+        return lines;
+    }
+    pub fn render_comment(&mut self, tilesize: f32) {
+        let lines = self.comment_lines(tilesize);
+        let bottom = screen_height() - if self.analysis_mode { 110.0 } else { 60.0 };
+        for (i, line) in lines.iter().enumerate() {
+            draw_text_ex(
+                line,
+                self.panel_x(tilesize),
+                bottom - (lines.len() - 1 - i) as f32 * 16.0,
+                TextParams{font: self.config.font, font_size: 14u16, color: Color::from_rgba(160, 200, 160, 255), ..Default::default()}
+            );
+        }
+    }
+    pub fn render_clocks(&mut self, tilesize: f32) {
+        if self.time_control.is_none() {
+            return;
+        }
+        for (i, color) in [Team::White, Team::Black].iter().enumerate() {
+            let clock = &self.clocks[color.as_int() as usize];
+            let x = self.panel_x(tilesize) + i as f32 * 125.0;
+            let running = clock.active;
+            let (bg, fg) = if running {
+                (Color::from_rgba(240, 240, 240, 255), Color::from_rgba(33, 32, 33, 255))
+            }
+            else {
+                (Color::from_rgba(64, 61, 57, 255), self.config.theme.title_color)
+            };
+            draw_rectangle(x, 5.0, 115.0, 35.0, bg);
+            if clock.remaining() < LOW_TIME {
+                draw_rectangle_lines(x, 5.0, 115.0, 35.0, 3.0, Color::from_rgba(200, 40, 40, 255));
+            }
+            draw_text_ex(
+                &format!("{} {}", color.as_string().chars().next().unwrap(), format_clock(clock.remaining())),
+                x + 8.0,
+                29.0,
+                TextParams{font: self.config.font, font_size: 20u16, color: fg, ..Default::default()}
+            );
+        }
+    }
+    pub fn panel_x(&self, tilesize: f32) -> f32 {
+        if self.analysis_mode {
+            return COLS as f32 * tilesize + EVAL_BAR_WIDTH + 10.0;
+        }
+        COLS as f32 * tilesize + 10.0
+    }
+    pub fn render_arrow(&self, initial: (isize, isize), end: (isize, isize), tilesize: f32, color: Color) {
+        let (start_x, start_y) = tile_to_screen(initial.0, initial.1, tilesize, self.flipped);
+        let (tip_x, tip_y) = tile_to_screen(end.0, end.1, tilesize, self.flipped);
+        let start = vec2(start_x, start_y) + tilesize * 0.5;
+        let tip = vec2(tip_x, tip_y) + tilesize * 0.5;
+        let dir = (tip - start).normalize_or_zero();
+        let normal = vec2(-dir.y, dir.x);
+        let head_len = tilesize * 0.35;
+        let base = tip - dir * head_len;
+        draw_line(start.x, start.y, base.x, base.y, tilesize * 0.15, color);
+        draw_triangle(tip, base + normal * head_len * 0.6, base - normal * head_len * 0.6, color);
+    }
+    pub fn render_analysis(&mut self, tilesize: f32) {
+        let progress = match &self.analysis {
+            Some(search) => search.progress(),
+            None => return
         };
-        let smaller_dim = screen_width().min(screen_height());
-        let x_shift = screen_width() - smaller_dim;
-        let y_shift = screen_height() - smaller_dim;
-        draw_rectangle(0.0, 0.0, screen_width() - x_shift, screen_height() - y_shift, Color::from_rgba(0, 0, 0, alpha));
+        // evaluations are relative to the side to move
+        let evaluation = if self.next_player == Team::White { progress.evaluation } else { -progress.evaluation };
+        let pawns = if progress.depth > 0 { self.algorithms.eval.to_centipawns(evaluation) / 100.0 } else { 0.0 };
+        let board_size = ROWS as f32 * tilesize;
+        let white_height = board_size * (0.5 + pawns.clamp(-10.0, 10.0) / 20.0);
+        let bar_x = COLS as f32 * tilesize;
+        draw_rectangle(bar_x, 0.0, EVAL_BAR_WIDTH, board_size, Color::from_rgba(64, 61, 57, 255));
+        let white_y = if self.flipped { 0.0 } else { board_size - white_height };
+        draw_rectangle(bar_x, white_y, EVAL_BAR_WIDTH, white_height, Color::from_rgba(240, 240, 240, 255));
 
-        let checkmate_text = "Checkmate";
-        let winner_text = format!("{} Wins", self.winner.as_string());
-        let checkmate_dims = measure_text(checkmate_text, Some(self.config.font), 75u16, 1.0);
-        let dims = measure_text(&winner_text, Some(self.config.font), 75u16, 1.0);
-        let text_pos = if self.win_screen_timer.runtime < 800 {
-            ((screen_width() - x_shift) / 2.0 - dims.width / 2.0, (screen_height() - y_shift) / 2.0 - dims.height / 2.0 - (54.0 - (self.win_screen_timer.runtime / 15) as f32))
+        if let Some(best_move) = &progress.best_move {
+            self.render_arrow((best_move.initial.row, best_move.initial.col), (best_move.end.row, best_move.end.col), tilesize, Color::from_rgba(21, 120, 27, 180));
         }
-        else {
-            ((screen_width() - x_shift) / 2.0 - dims.width / 2.0, (screen_height() - y_shift) / 2.0 - dims.height / 2.0)
+
+        let mut lines = Vec::from([format!("depth {} eval {:+.2}", progress.depth, pawns)]);
+        if let Some(line) = progress.lines.first() {
+            let mut text = String::new();
+            for san in self.board.line_to_san(&line.moves) {
+                if text.len() + san.len() > 20 {
+                    lines.push(text);
+                    text = String::new();
+                }
+                text.push_str(&san);
+                text.push(' ');
+            }
+            lines.push(text);
+        }
+        let y_offset = screen_height() - 10.0 - lines.len() as f32 * 20.0;
+        for (i, line) in lines.iter().enumerate() {
+            draw_text_ex(
+                line,
+                self.panel_x(tilesize),
+                y_offset + i as f32 * 20.0,
+                TextParams{font: self.config.font, font_size: 16u16, color: self.config.theme.title_color, ..Default::default()}
+            );
+        }
+    }
+    pub fn render_move_list(&mut self, tilesize: f32) {
+        let mut top = self.panel_top() + 5.0;
+        if self.show_stats {
+            top += 160.0;
+        }
+        if self.algorithms.multi_pv > 1 {
+            top += self.algorithms.multi_pv as f32 * 20.0 + 10.0;
+        }
+        let comment_height = match self.comment_lines(tilesize).len() {
+            0 => 0.0,
+            n => n as f32 * 16.0 + 10.0
         };
+        let bottom = screen_height() - if self.analysis_mode { 110.0 } else { 60.0 } - comment_height;
+        let x = self.panel_x(tilesize);
+        self.move_list.rect = (x, top, screen_width() - x, (bottom - top).max(0.0));
+
+        let sans: Vec<String> = self.history.iter().skip(1).map(|snapshot| {
+            let glyphs: String = snapshot.nags.iter().filter_map(|nag| nag_glyph(*nag)).collect();
+            snapshot.san.clone() + &glyphs
+        }).collect();
+        self.move_list.draw(&sans, self.cursor, self.config.font, self.config.theme.title_color, Color::from_rgba(80, 80, 80, 255));
+    }
+    pub fn render_thinking(&mut self, tilesize: f32) {
+        if let Some(search) = &self.search {
+            let progress = search.progress();
+            let mut lines = Vec::from(["Thinking...".to_string()]);
+            if let Some(best_move) = progress.best_move {
+                lines.push(format!("depth {} {}", progress.depth, best_move.to_uci()));
+            }
+            for (i, line) in lines.iter().enumerate() {
+                draw_text_ex(
+                    line,
+                    self.panel_x(tilesize),
+                    screen_height() - 30.0 + i as f32 * 20.0 - (lines.len() - 1) as f32 * 20.0,
+                    TextParams{font: self.config.font, font_size: 16u16, color: self.config.theme.title_color, ..Default::default()}
+                );
+            }
+        }
+    }
+    pub fn render_notice(&mut self, tilesize: f32) {
+        if self.notice.is_empty() {
+            return;
+        }
+        let board_size = COLS as f32 * tilesize;
+        let dims = measure_text(&self.notice, Some(self.config.font), 20u16, 1.0);
+        let y = ROWS as f32 * tilesize / 2.0 - 20.0;
+        draw_rectangle(board_size / 2.0 - dims.width / 2.0 - 10.0, y, dims.width + 20.0, 40.0, Color::from_rgba(33, 32, 33, 220));
+        draw_text_ex(
+            &self.notice,
+            board_size / 2.0 - dims.width / 2.0,
+            y + 20.0 + dims.height / 2.0,
+            TextParams{font: self.config.font, font_size: 20u16, color: self.config.theme.title_color, ..Default::default()}
+        );
+    }
+    pub fn result_panel(&self, tilesize: f32) -> (f32, f32, f32, f32) {
+        // centred on the board, leaving the outer ring of squares visible
+        (tilesize, 2.0 * tilesize, 6.0 * tilesize, 4.0 * tilesize)
+    }
+    pub fn result_buttons(&self, tilesize: f32) -> Vec<(ResultAction, (f32, f32, f32, f32))> {
+        let (x, y, w, h) = self.result_panel(tilesize);
+        let button_width = (w - 30.0) / 2.0;
+        let button_height = h * 0.18;
+        let top = y + h * 0.45;
+        let mut buttons = Vec::new();
+        for (i, action) in ResultAction::all().iter().enumerate() {
+            let bx = x + 10.0 + (i % 2) as f32 * (button_width + 10.0);
+            let by = top + (i / 2) as f32 * (button_height + 10.0);
+            buttons.push((*action, (bx, by, button_width, button_height)));
+        }
+        return buttons;
+    }
+    pub fn result_action_at(&self, pos: (f32, f32), tilesize: f32) -> Option<ResultAction> {
+        let (x, y) = pos;
+        self.result_buttons(tilesize).into_iter()
+            .find(|(_, rect)| (x > rect.0) && (x < rect.0 + rect.2) && (y > rect.1) && (y < rect.1 + rect.3))
+            .map(|(action, _)| action)
+    }
+    pub fn in_result_panel(&self, pos: (f32, f32), tilesize: f32) -> bool {
+        let (x, y, w, h) = self.result_panel(tilesize);
+        (pos.0 > x) && (pos.0 < x + w) && (pos.1 > y) && (pos.1 < y + h)
+    }
+    pub async fn run_result_action(&mut self, action: ResultAction) {
+        match action {
+            ResultAction::Rematch => {
+                if self.use_ai {
+                    self.set_human_side(self.human_side.other());
+                }
+                self.reset().await;
+            },
+            ResultAction::Analyse => {
+                self.cancel_search();
+                self.show_result = false;
+                self.use_ai = false;
+                self.analysis_mode = true;
+                self.time_control = None;
+                self.goto(0);
+            },
+            ResultAction::SavePgn => {
+                self.result_message = match self.save_game(SAVED_PGN) {
+                    Ok(()) => format!("Saved to {}", SAVED_PGN),
+                    Err(err) => err
+                };
+            },
+            ResultAction::MainMenu => self.wants_menu = true
+        }
+    }
+    pub fn render_result(&mut self, tilesize: f32) {
+        // the board dims in over the first half second
+        let fade = (self.win_screen_timer.runtime as f32 / 500.0).min(1.0);
+        let board_size = COLS as f32 * tilesize;
+        draw_rectangle(0.0, 0.0, board_size, ROWS as f32 * tilesize, Color::from_rgba(0, 0, 0, (fade * 150.0) as u8));
+
+        let (x, y, w, h) = self.result_panel(tilesize);
+        draw_rectangle(x, y, w, h, Color::from_rgba(33, 32, 33, (fade * 240.0) as u8));
+        draw_rectangle_lines(x, y, w, h, 2.0, self.config.theme.bg.light);
+
+        let winner_text = if self.winner == Team::None { "Draw".to_string() } else { format!("{} Wins", self.winner.as_string()) };
+        let reason_text = self.end_reason.map_or(String::new(), |reason| reason.as_string());
+        let title_size = (tilesize * 0.6) as u16;
+        let reason_size = (tilesize * 0.25) as u16;
+        let dims = measure_text(&winner_text, Some(self.config.font), title_size, 1.0);
         draw_text_ex(
             &winner_text,
-            text_pos.0,
-            text_pos.1 + dims.height - 20.0,
-            TextParams{font: self.config.font, font_size: 75u16, color: self.config.theme.title_color, ..Default::default()}
+            x + w / 2.0 - dims.width / 2.0,
+            y + h * 0.22,
+            TextParams{font: self.config.font, font_size: title_size, color: self.config.theme.title_color, ..Default::default()}
         );
+        let dims = measure_text(&reason_text, Some(self.config.font), reason_size, 1.0);
         draw_text_ex(
-            checkmate_text,
-            (screen_width() - x_shift) / 2.0 - checkmate_dims.width / 2.0,
-            text_pos.1 - checkmate_dims.height - 20.0,
-            TextParams{font: self.config.font, font_size: 75u16, color: self.config.theme.title_color, ..Default::default()}
+            &reason_text,
+            x + w / 2.0 - dims.width / 2.0,
+            y + h * 0.35,
+            TextParams{font: self.config.font, font_size: reason_size, color: self.config.theme.title_color, ..Default::default()}
         );
+
+        let mousepos = mouse_position();
+        for (action, rect) in self.result_buttons(tilesize).iter() {
+            let hovered = self.result_action_at(mousepos, tilesize) == Some(*action);
+            let bg = if hovered { Color::from_rgba(240, 240, 240, 255) } else { Color::from_rgba(200, 200, 200, 255) };
+            draw_rectangle(rect.0, rect.1, rect.2, rect.3, bg);
+            let font_size = (rect.3 * 0.5) as u16;
+            let text = action.as_string();
+            let dims = measure_text(&text, Some(self.config.font), font_size, 1.0);
+            draw_text_ex(
+                &text,
+                rect.0 + rect.2 / 2.0 - dims.width / 2.0,
+                rect.1 + rect.3 / 2.0 + dims.height / 4.0,
+                TextParams{font: self.config.font, font_size: font_size, color: Color::from_rgba(90, 90, 90, 255), ..Default::default()}
+            );
+        }
+        if !self.result_message.is_empty() {
+            let dims = measure_text(&self.result_message, Some(self.config.font), 16u16, 1.0);
+            draw_text_ex(
+                &self.result_message,
+                x + w / 2.0 - dims.width / 2.0,
+                y + h - 10.0,
+                TextParams{font: self.config.font, font_size: 16u16, color: self.config.theme.title_color, ..Default::default()}
+            );
+        }
     }
     pub async fn draw(&mut self, tilesize: f32) {
         clear_background(Color::from_rgba(33, 32, 33, 255));
         self.render_bg(tilesize);
         self.render_last_move(tilesize);
+        self.render_check(tilesize);
         self.render_valid_moves(tilesize);
+        self.render_premove(tilesize);
         self.render_hover(tilesize);
-        self.render_pieces(tilesize).await;
-        if self.winner != Team::None {
+        self.render_pieces(tilesize);
+        self.render_animation(tilesize);
+        self.render_annotations(tilesize);
+        self.render_analysis(tilesize);
+        self.render_stats(tilesize);
+        self.render_lines(tilesize);
+        self.render_move_list(tilesize);
+        self.render_comment(tilesize);
+        self.render_headers(tilesize);
+        self.render_thinking(tilesize);
+        self.render_clocks(tilesize);
+        self.render_notice(tilesize);
+        if self.show_result {
             if !self.win_screen_timer.active && !self.win_screen_timer.finished {
                 self.win_screen_timer.activate();
             }
             self.win_screen_timer.update();
-            self.render_win_screen();
+            self.render_result(tilesize);
         }
     }
+}
+
+
+fn pgn_headers(pgn: &PgnGame) -> Vec<String> {
+    let player = |name: &str, elo: &str| match (pgn.tag(name), pgn.tag(elo)) {
+        (Some(name), Some(elo)) if !elo.is_empty() => format!("{} ({})", name, elo),
+        (Some(name), _) => name.to_string(),
+        (None, _) => "?".to_string()
+    };
+    let mut lines = Vec::from([format!("{} - {}", player("White", "WhiteElo"), player("Black", "BlackElo"))]);
+    let mut event = pgn.tag("Event").unwrap_or("?").to_string();
+    if let Some(round) = pgn.tag("Round") {
+        event.push_str(&format!(", round {}", round));
+    }
+    if let Some(date) = pgn.tag("Date") {
+        event.push_str(&format!(", {}", date));
+    }
+    lines.push(event);
+    let opening: Vec<&str> = [pgn.tag("ECO"), pgn.tag("Opening"), Some(pgn.result.as_str())].iter().flatten().copied().collect();
+    lines.push(opening.join(" "));
+    return lines;
+}
+
+fn join_comment(annotations: &Annotations, comment: &str) -> String {
+    let commands = annotations.to_comment();
+    if commands.is_empty() || comment.is_empty() {
+        return commands + comment;
+    }
+    format!("{} {}", commands, comment)
 }
\ No newline at end of file
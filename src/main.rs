@@ -1,76 +1,169 @@
 use macroquad::prelude::*;
+use ::rand::{thread_rng, Rng};
 #[path = "game.rs"] mod game;
 use game::*;
 #[path = "menu.rs"] mod menu;
 use menu::MainMenu;
+#[path = "editor.rs"] mod editor;
+use editor::{Editor, SetupAction};
+#[path = "browser.rs"] mod browser;
+use browser::Browser;
+#[path = "uci.rs"] mod uci;
+#[path = "arena.rs"] mod arena;
+#[path = "tuner.rs"] mod tuner;
 
 
 async fn check_events(game: &mut Game, tilesize: f32) {
-    if is_mouse_button_pressed(MouseButton::Left) && (!game.use_ai || (game.next_player != game.algorithms.perspective)) {
-        let mousepos = mouse_position();
-        game.dragger.update_mouse(mousepos);
-        let clicked_col = (game.dragger.mousex / tilesize) as isize;
-        let clicked_row = (game.dragger.mousey / tilesize) as isize;
-        if inrange(clicked_row) && inrange(clicked_col) && game.board.tiles[clicked_row as usize][clicked_col as usize].has_piece() {
-            if game.board.tiles[clicked_row as usize][clicked_col as usize].piece().color == game.next_player {
-                game.dragger.save_initial(mousepos, tilesize);
-                game.dragger.begin_drag(game.board.tiles[clicked_row as usize][clicked_col as usize].piece());
+    if game.show_result {
+        // the result overlay takes all input until it is dismissed
+        if is_mouse_button_pressed(MouseButton::Left) {
+            let mousepos = mouse_position();
+            match game.result_action_at(mousepos, tilesize) {
+                Some(action) => game.run_result_action(action).await,
+                None if !game.in_result_panel(mousepos, tilesize) => game.show_result = false,
+                None => ()
             }
         }
+        if is_key_pressed(KeyCode::Escape) {
+            game.show_result = false;
+        }
+        return;
     }
-    if is_mouse_button_released(MouseButton::Left) && (!game.use_ai || (game.next_player != game.algorithms.perspective)) {
+    if is_mouse_button_pressed(MouseButton::Left) {
         let mousepos = mouse_position();
-        if game.dragger.dragging {
-            game.dragger.update_mouse(mousepos);
-            let released_col = (game.dragger.mousex / tilesize) as isize;
-            let released_row = (game.dragger.mousey / tilesize) as isize;
-
-            let mut initial = Tile::new();
-            let mut final_ = Tile::new();
-            initial.init(game.dragger.init_row, game.dragger.init_col, None);
-            final_.init(released_row, released_col, None);
-            let action = Move::new(initial, final_);
-
-            if game.board.is_valid(game.dragger.piece.unwrap(), action.clone()) {
-                let captured = game.board.tiles[released_row as usize][released_col as usize].has_piece();
-                game.board.execute_move(&mut game.dragger.piece.unwrap(), action.clone(), false, false);
-                // for i in 0..ROWS {
-                //     for j in 0..COLS {
-                //         if game.board.tiles[i][j].has_piece() && (*game.board.tiles[i][j].piece() == game.dragger.piece.unwrap()) {
-                //             game.board.tiles[i][j].piece().has_moved = true;
-                //         }
-                //     }
-                // }
-                game.board.set_en_passant(&mut game.dragger.piece.unwrap(), action);
-                game.playsound(captured);
-                game.draw(tilesize).await;
-                game.next_turn();
+        game.dragger.update_mouse(mousepos);
+        let (clicked_row, clicked_col) = screen_to_tile(mousepos, tilesize, game.flipped);
+        if inrange(clicked_row) && inrange(clicked_col) {
+            match game.board.tiles[clicked_row as usize][clicked_col as usize].present_piece {
+                Some(piece) if piece.color == game.controlled_side() => {
+                    game.selected = Some((clicked_row, clicked_col));
+                    game.dragger.save_initial(mousepos, tilesize, game.flipped);
+                    game.dragger.begin_drag(&piece);
+                },
+                _ => {
+                    // second click of a click-to-move
+                    if let Some(selected) = game.selected.take() {
+                        game.try_move(selected, (clicked_row, clicked_col));
+                    }
+                }
             }
         }
+    }
+    if is_mouse_button_released(MouseButton::Left) && game.dragger.dragging {
+        let initial = (game.dragger.init_row, game.dragger.init_col);
+        let released = screen_to_tile(mouse_position(), tilesize, game.flipped);
+        // dropping back on the same square leaves the piece selected for a click-to-move
+        if (released != initial) && game.try_move(initial, released) {
+            game.selected = None;
+        }
         game.dragger.end_drag();
     }
+    if is_mouse_button_pressed(MouseButton::Right) {
+        game.selected = None;
+        game.premove = None;
+        game.dragger.end_drag();
+        let start = screen_to_tile(mouse_position(), tilesize, game.flipped);
+        game.annotation_start = if inrange(start.0) && inrange(start.1) { Some(start) } else { None };
+    }
+    if is_mouse_button_released(MouseButton::Right) {
+        let end = screen_to_tile(mouse_position(), tilesize, game.flipped);
+        if let Some(start) = game.annotation_start.take() {
+            if inrange(end.0) && inrange(end.1) {
+                let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift) || is_key_down(KeyCode::LeftControl);
+                let alt = is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt);
+                game.annotate(start, end, MarkColor::from_modifiers(shift, alt));
+            }
+        }
+    }
+    if is_mouse_button_pressed(MouseButton::Left) {
+        if let Some(ply) = game.move_list.ply_at(mouse_position(), game.history.len() - 1) {
+            game.goto(ply);
+        }
+    }
+    if game.move_list.contains(mouse_position()) && (mouse_wheel().1 != 0.0) {
+        game.move_list.scroll_by(-mouse_wheel().1.signum() as isize, game.history.len() - 1);
+    }
+    if is_key_pressed(KeyCode::Left) {
+        game.step_history(-1);
+    }
+    if is_key_pressed(KeyCode::Right) {
+        game.step_history(1);
+    }
+    if is_key_pressed(KeyCode::Home) {
+        game.goto(0);
+    }
+    if is_key_pressed(KeyCode::End) {
+        game.goto(game.history.len() - 1);
+    }
+    if is_key_pressed(KeyCode::F) {
+        game.flipped = !game.flipped;
+    }
     if is_key_pressed(KeyCode::T) {
         game.config.change_theme();
     }
+    if is_key_pressed(KeyCode::G) {
+        game.config.change_piece_set().await;
+    }
+    if is_key_pressed(KeyCode::P) {
+        game.use_ponder = !game.use_ponder;
+    }
+    if is_key_pressed(KeyCode::M) {
+        game.algorithms.multi_pv = game.algorithms.multi_pv % MAX_MULTI_PV + 1;
+    }
+    if is_key_pressed(KeyCode::I) {
+        game.show_stats = !game.show_stats;
+    }
     if is_key_pressed(KeyCode::R) {
         game.reset().await;
     }
-    if is_key_pressed(KeyCode::Z) && !game.use_ai {
+    if is_key_pressed(KeyCode::Z) {
         game.undo_move();
     }
-    let mousepos = mouse_position();
-    game.set_hover((mousepos.1 / tilesize) as isize, (mousepos.0 / tilesize) as isize);
-    if !game.use_ai || (game.next_player != game.algorithms.perspective) {
-        if game.dragger.dragging {
-            game.dragger.update_mouse(mousepos);
-            game.dragger.draw().await;
+    if is_key_pressed(KeyCode::Y) {
+        game.redo_move();
+    }
+    if is_key_pressed(KeyCode::A) && !game.use_ai {
+        // lets the engine annotate the positions while stepping through a game
+        game.analysis_mode = !game.analysis_mode;
+    }
+    if is_key_pressed(KeyCode::V) {
+        game.start_review();
+    }
+    if is_key_pressed(KeyCode::X) {
+        game.resign();
+    }
+    if is_key_pressed(KeyCode::D) {
+        game.offer_draw();
+    }
+    if is_key_pressed(KeyCode::S) {
+        if let Err(err) = game.save_game(SAVED_PGN) {
+            eprintln!("{}", err);
         }
     }
+    if is_key_pressed(KeyCode::O) {
+        if let Err(err) = game.load_game(SAVED_PGN) {
+            eprintln!("{}", err);
+        }
+    }
+    let mousepos = mouse_position();
+    let (hover_row, hover_col) = screen_to_tile(mousepos, tilesize, game.flipped);
+    game.set_hover(hover_row, hover_col);
+    if game.dragger.dragging {
+        game.dragger.update_mouse(mousepos);
+    }
 }
 
-async fn update_moves(game: &mut Game, tilesize: f32) {
-    if (game.next_player == game.algorithms.perspective) && game.use_ai {
-        game.compute_move(tilesize).await;
+fn update_moves(game: &mut Game) {
+    game.update_animation();
+    game.update_notice();
+    game.update_review();
+    game.update_clocks();
+    if (game.next_player == game.algorithms.perspective) && game.use_ai && game.is_live() {
+        game.update_search();
+    }
+    game.play_premove();
+    if game.analysis_mode {
+        game.update_analysis();
     }
 }
 
@@ -79,7 +172,7 @@ async fn draw(menu: &mut MainMenu, game: &mut Game, tilesize: f32) {
     menu.draw();
     game.draw(tilesize).await;
     if game.dragger.dragging {
-        game.dragger.draw().await;
+        game.render_dragged(tilesize);
     }
 }
 
@@ -87,21 +180,100 @@ async fn draw(menu: &mut MainMenu, game: &mut Game, tilesize: f32) {
 
 
 
-#[macroquad::main("Chess")]
-async fn main() {
+fn main() {
+    if std::env::args().any(|arg| arg == "--uci") {
+        uci::run();
+        return;
+    }
+    let args: Vec<String> = std::env::args().collect();
+    if args.iter().any(|arg| arg == "--review") {
+        review_cli(&args);
+        return;
+    }
+    if args.iter().any(|arg| arg == "--match") {
+        arena::run(&args);
+        return;
+    }
+    if args.iter().any(|arg| arg == "--tune") {
+        tuner::run(&args);
+        return;
+    }
+    macroquad::Window::new("Chess", run());
+}
+
+async fn start_game(menu: &MainMenu, game: &mut Game) {
+    game.apply_settings(menu.settings.clone()).await;
+    if let Some(path) = &menu.load_path {
+        match game.load_game(path) {
+            Ok(()) => return,
+            Err(err) => {
+                eprintln!("starting a new game instead: {}", err);
+                game.reset().await;
+            }
+        }
+    }
+    game.use_ai = menu.should_use_ai;
+    if game.use_ai {
+        let side = match menu.side {
+            Team::None => if thread_rng().gen_bool(0.5) { Team::White } else { Team::Black },
+            side => side
+        };
+        game.set_human_side(side);
+    }
+    else {
+        game.set_human_side(Team::White);
+    }
+    game.analysis_mode = menu.should_analyse;
+    game.time_control = if game.analysis_mode { None } else { menu.time_control };
+    if let Err(err) = game.start_position(menu.start_fen.as_deref()) {
+        eprintln!("starting from the initial position instead: {}", err);
+        game.start_position(None).unwrap();
+    }
+}
+
+async fn choose_game(menu: &mut MainMenu, game: &mut Game) {
+    loop {
+        menu.show().await;
+        if menu.should_browse {
+            // a game picked from a collection is viewed as it is, without the menu's setup
+            game.apply_settings(menu.settings.clone()).await;
+            if Browser::new().run(game).await {
+                return;
+            }
+            continue;
+        }
+        if !menu.should_edit {
+            break;
+        }
+        // backing out of the editor returns to the menu
+        if let Some((fen, action)) = Editor::new().run(game).await {
+            menu.start_fen = Some(fen);
+            menu.should_use_ai = action == SetupAction::PlayAi;
+            menu.should_analyse = action == SetupAction::Analyse;
+            break;
+        }
+    }
+    start_game(menu, game).await;
+}
+
+async fn run() {
     request_new_screen_size(640., 400.);
     let mut menu = MainMenu::new().await;
     menu.show_load();
     let mut game = Game::new().await;
-    menu.show().await;
-    game.use_ai = menu.should_use_ai;
-    game.init();
+    choose_game(&mut menu, &mut game).await;
     let mut tilesize: f32;
 
     loop {
+        if game.wants_menu {
+            game.reset().await;
+            // keys like T and G change settings during a game
+            menu.settings = game.config.settings.clone();
+            choose_game(&mut menu, &mut game).await;
+        }
         tilesize = screen_width().min(screen_height()) / 8.0;
         check_events(&mut game, tilesize).await;
-        update_moves(&mut game, tilesize).await;
+        update_moves(&mut game);
         draw(&mut menu, &mut game, tilesize).await;
         next_frame().await
     }
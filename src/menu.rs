@@ -1,46 +1,75 @@
 use macroquad::prelude::*;
 #[path = "constants.rs"] mod constants;
-use constants::BG_COLOR;
+use constants::{BG_COLOR, MAX_MULTI_PV, AUTOSAVE_PGN};
+use std::path::Path;
+use super::game::{TimeControl, Team, Settings, Theme, TextureAtlas, saved_games};
 
+const VOLUMES: [f32; 4] = [0.25, 0.5, 0.75, 1.0];
+const ANIMATION_SPEEDS: [u64; 5] = [0, 100, 150, 250, 400];
+const SEARCH_DEPTHS: [isize; 5] = [2, 3, 4, 5, 6];
+const RESIGN_THRESHOLDS: [f32; 4] = [0.0, 4.0, 6.0, 9.0];
+const RESIGN_MOVES: [usize; 4] = [1, 3, 5, 8];
+const MAX_LISTED_SAVES: usize = 8;
 
-struct Button {
+
+pub struct Button {
     font: Font,
     rect: (f32, f32, f32, f32),
     text: String,
 }
 
 impl Button {
-    fn new(pos: (f32, f32), size: (f32, f32), text: String, font: Font) -> Self {
+    pub fn new(pos: (f32, f32), size: (f32, f32), text: String, font: Font) -> Self {
         Self {
             font: font,
             rect: (pos.0, pos.1, size.0, size.1),
             text: text,
         }
     }
-    fn clicked(&mut self, mousepos: (f32, f32)) -> bool {
+    pub fn clicked(&mut self, mousepos: (f32, f32)) -> bool {
         let (x, y) = mousepos;
         if (x < self.rect.0 + self.rect.2) && (y < self.rect.1 + self.rect.3) && (x > self.rect.0) && (y > self.rect.1) {
             return true;
         }
         return false;
     }
-    fn draw(&self) {
+    pub fn draw(&self) {
         draw_rectangle(self.rect.0, self.rect.1, self.rect.2, self.rect.3, Color::from_rgba(200, 200, 200, 255));
-        let dims = measure_text(&self.text, Some(self.font), 96u16, 1.0);
+        let font_size = (self.rect.3 * 0.7) as u16;
+        let dims = measure_text(&self.text, Some(self.font), font_size, 1.0);
         draw_text_ex(
             &self.text,
             self.rect.2 / 2.0 - dims.width / 2.0 + self.rect.0,
             self.rect.3 / 2.0 + dims.height / 4.0 + self.rect.1,
-            TextParams{font: self.font, font_size: 96u16, color: Color::from_rgba(150, 150, 150, 255), ..Default::default()}
+            TextParams{font: self.font, font_size: font_size, color: Color::from_rgba(150, 150, 150, 255), ..Default::default()}
         )
     }
 }
 
 
 
+#[derive(PartialEq)]
+enum Page {
+    Main,
+    Settings,
+    Load
+}
+
+
 pub struct MainMenu {
     pub active: bool,
     pub should_use_ai: bool,
+    pub should_analyse: bool,
+    pub should_edit: bool,
+    pub should_browse: bool,
+    pub start_fen: Option<String>,
+    pub time_control: Option<TimeControl>,
+    time_control_idx: usize,
+    pub side: Team,
+    pub settings: Settings,
+    pub load_path: Option<String>,
+    saves: Vec<String>,
+    page: Page,
     font: Font,
     buttons: Vec<Button>
 }
@@ -50,6 +79,17 @@ impl MainMenu {
         Self {
             active: false,
             should_use_ai: false,
+            should_analyse: false,
+            should_edit: false,
+            should_browse: false,
+            start_fen: None,
+            time_control: None,
+            time_control_idx: 0,
+            side: Team::White,
+            settings: Settings::load(),
+            load_path: None,
+            saves: Vec::new(),
+            page: Page::Main,
             font: load_ttf_font("assets/Monaco.ttf").await.unwrap(),
             buttons: Vec::new()
         }
@@ -66,11 +106,14 @@ impl MainMenu {
         )
     }
     pub async fn show(&mut self) {
-        let button_width = 700.0;
-        self.buttons = Vec::from([
-            Button::new((screen_width() / 2.0 - button_width / 2.0, screen_height() / 2.0 - 100.0), (button_width, 150.0), "Fight AI".to_string(), self.font),
-            Button::new((screen_width() / 2.0 - button_width / 2.0, screen_height() / 2.0 + 100.0), (button_width, 150.0), "Begin PVP".to_string(), self.font),
-        ]);
+        self.page = Page::Main;
+        self.should_use_ai = false;
+        self.should_analyse = false;
+        self.should_edit = false;
+        self.should_browse = false;
+        self.start_fen = None;
+        self.load_path = None;
+        self.rebuild();
         self.active = true;
         while self.active {
             if is_mouse_button_pressed(MouseButton::Left) {
@@ -81,6 +124,92 @@ impl MainMenu {
             next_frame().await;
         }
     }
+    fn layout(&mut self, rows: Vec<Vec<String>>) {
+        // rows share the space below the title, buttons in a row split its width
+        let top = 140.0;
+        let row_height = (screen_height() - 20.0 - top) / rows.len() as f32;
+        let button_height = (row_height - 10.0).min(100.0);
+        let width = (screen_width() - 40.0).min(700.0);
+        self.buttons.clear();
+        for (i, row) in rows.into_iter().enumerate() {
+            let n = row.len() as f32;
+            let button_width = (width - 20.0 * (n - 1.0)) / n;
+            for (j, text) in row.into_iter().enumerate() {
+                let x = screen_width() / 2.0 - width / 2.0 + j as f32 * (button_width + 20.0);
+                self.buttons.push(Button::new((x, top + i as f32 * row_height), (button_width, button_height), text, self.font));
+            }
+        }
+    }
+    fn rebuild(&mut self) {
+        let rows = match self.page {
+            Page::Main => Vec::from([
+                Vec::from(["Fight AI".to_string()]),
+                Vec::from(["Begin PVP".to_string()]),
+                Vec::from(["Analysis".to_string(), "Set up position".to_string()]),
+                Vec::from([self.time_control_text(), self.side_text()]),
+                Vec::from([self.continue_text(), "Load game...".to_string()]),
+                Vec::from(["Browse games".to_string(), "Settings".to_string()]),
+            ]),
+            Page::Settings => {
+                let on_off = |value: bool| if value { "On" } else { "Off" };
+                let animation = match self.settings.animation_ms {
+                    0 => "Off".to_string(),
+                    ms => format!("{}ms", ms)
+                };
+                Vec::from([
+                    Vec::from([format!("Theme: {}", self.settings.theme)]),
+                    Vec::from([format!("Pieces: {}", self.settings.piece_set)]),
+                    Vec::from([format!("Sound: {}", on_off(self.settings.sound)), format!("Volume: {}%", (self.settings.volume * 100.0) as i32)]),
+                    Vec::from([format!("Coordinates: {}", on_off(self.settings.coordinates)), format!("Animation: {}", animation)]),
+                    Vec::from([format!("AI depth: {}", self.settings.search_depth), format!("Ponder: {}", on_off(self.settings.ponder))]),
+                    Vec::from([format!("AI lines: {}", self.settings.multi_pv)]),
+                    Vec::from([self.resign_text(), format!("After: {} moves", self.settings.resign_moves)]),
+                    Vec::from(["Back".to_string()]),
+                ])
+            },
+            Page::Load => {
+                let mut rows: Vec<Vec<String>> = self.saves.iter().map(|path| {
+                    Vec::from([Path::new(path).file_stem().map_or(path.clone(), |stem| stem.to_string_lossy().to_string())])
+                }).collect();
+                if rows.is_empty() {
+                    rows.push(Vec::from(["No saved games".to_string()]));
+                }
+                rows.push(Vec::from(["Back".to_string()]));
+                rows
+            }
+        };
+        self.layout(rows);
+    }
+    fn continue_text(&self) -> String {
+        if Path::new(AUTOSAVE_PGN).exists() { "Continue last game".to_string() } else { "No last game".to_string() }
+    }
+    fn resign_text(&self) -> String {
+        if self.settings.resign_threshold <= 0.0 {
+            return "AI resigns: Never".to_string();
+        }
+        format!("AI resigns: -{}", self.settings.resign_threshold)
+    }
+    fn time_control_text(&self) -> String {
+        format!("Clock: {}", TimeControl::label(&self.time_control))
+    }
+    pub fn cycle_time_control(&mut self) {
+        let presets = TimeControl::presets();
+        self.time_control_idx = (self.time_control_idx + 1) % presets.len();
+        self.time_control = presets[self.time_control_idx];
+    }
+    fn side_text(&self) -> String {
+        match self.side {
+            Team::None => "Side: Random".to_string(),
+            side => format!("Side: {}", side.as_string())
+        }
+    }
+    pub fn cycle_side(&mut self) {
+        self.side = match self.side {
+            Team::White => Team::Black,
+            Team::Black => Team::None,
+            Team::None => Team::White
+        };
+    }
     pub fn start_pvp(&mut self) {
         self.should_use_ai = false;
         self.active = false;
@@ -91,31 +220,99 @@ impl MainMenu {
     }
     pub fn on_click(&mut self) {
         if self.active {
-            for (i, button) in self.buttons.iter_mut().enumerate() {
-                let was_clicked = button.clicked(mouse_position());
-                if was_clicked {
-                    match i {
-                        0 => {
-                            self.should_use_ai = true;
-                            self.active = false;
-                        },
-                        _ => {
-                            self.should_use_ai = false;
-                            self.active = false;
-                        }
-                    }
+            let clicked = self.buttons.iter_mut().position(|button| button.clicked(mouse_position()));
+            if let Some(i) = clicked {
+                match self.page {
+                    Page::Main => self.on_main_click(i),
+                    Page::Settings => self.on_settings_click(i),
+                    Page::Load => self.on_load_click(i)
+                }
+                self.rebuild();
+            }
+        }
+    }
+    fn on_main_click(&mut self, i: usize) {
+        match i {
+            0 => {
+                self.should_use_ai = true;
+                self.active = false;
+            },
+            2 => {
+                self.should_use_ai = false;
+                self.should_analyse = true;
+                self.active = false;
+            },
+            3 => {
+                self.should_edit = true;
+                self.active = false;
+            },
+            4 => self.cycle_time_control(),
+            5 => self.cycle_side(),
+            6 => {
+                if Path::new(AUTOSAVE_PGN).exists() {
+                    self.load_path = Some(AUTOSAVE_PGN.to_string());
+                    self.active = false;
                 }
+            },
+            7 => {
+                self.saves = saved_games().into_iter().take(MAX_LISTED_SAVES).collect();
+                self.page = Page::Load;
+            },
+            8 => {
+                self.should_browse = true;
+                self.active = false;
+            },
+            9 => self.page = Page::Settings,
+            _ => {
+                self.should_use_ai = false;
+                self.active = false;
+            }
+        }
+    }
+    fn on_settings_click(&mut self, i: usize) {
+        let settings = &mut self.settings;
+        match i {
+            0 => {
+                let names: Vec<String> = Theme::all().into_iter().map(|theme| theme.name).collect();
+                settings.theme = next_of(&names, &settings.theme);
+            },
+            1 => settings.piece_set = next_of(&TextureAtlas::piece_sets(), &settings.piece_set),
+            2 => settings.sound = !settings.sound,
+            3 => settings.volume = next_of(&VOLUMES, &settings.volume),
+            4 => settings.coordinates = !settings.coordinates,
+            5 => settings.animation_ms = next_of(&ANIMATION_SPEEDS, &settings.animation_ms),
+            6 => settings.search_depth = next_of(&SEARCH_DEPTHS, &settings.search_depth),
+            7 => settings.ponder = !settings.ponder,
+            8 => settings.multi_pv = settings.multi_pv % MAX_MULTI_PV + 1,
+            9 => settings.resign_threshold = next_of(&RESIGN_THRESHOLDS, &settings.resign_threshold),
+            10 => settings.resign_moves = next_of(&RESIGN_MOVES, &settings.resign_moves),
+            _ => {
+                settings.save();
+                self.page = Page::Main;
             }
         }
     }
+    fn on_load_click(&mut self, i: usize) {
+        match self.saves.get(i) {
+            Some(path) => {
+                self.load_path = Some(path.clone());
+                self.active = false;
+            },
+            None => self.page = Page::Main
+        }
+    }
     pub fn draw(&self) {
         if self.active {
-            let text = "Chess";
+            let text = match self.page {
+                Page::Main => "Chess",
+                Page::Settings => "Settings",
+                Page::Load => "Load Game"
+            };
             let dims = measure_text(text, Some(self.font), 100u16, 1.0);
             draw_text_ex(
                 text,
                 screen_width() / 2.0 - dims.width / 2.0,
-                screen_height() / 2.0 - 200.0,
+                110.0,
                 TextParams{font: self.font, font_size: 100u16, color: WHITE, ..Default::default()}
             );
             for button in self.buttons.iter() {
@@ -123,4 +320,13 @@ impl MainMenu {
             }
         }
     }
+}
+
+
+fn next_of<T: PartialEq + Clone>(options: &[T], current: &T) -> T {
+    // an unknown value starts over from the first option
+    match options.iter().position(|option| option == current) {
+        Some(idx) => options[(idx + 1) % options.len()].clone(),
+        None => options[0].clone()
+    }
 }
\ No newline at end of file
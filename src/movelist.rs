@@ -0,0 +1,97 @@
+use macroquad::prelude::*;
+
+
+const ROW_HEIGHT: f32 = 18.0;
+const NUMBER_WIDTH: f32 = 40.0;
+const MOVE_WIDTH: f32 = 85.0;
+
+
+pub struct MoveList {
+    pub scroll: usize,
+    pub rect: (f32, f32, f32, f32),
+    // plies played before the first listed move, non-zero for games set up from a position
+    pub offset: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        Self {
+            scroll: 0,
+            rect: (0.0, 0.0, 0.0, 0.0),
+            offset: 0
+        }
+    }
+    fn pad(&self) -> usize {
+        // a game starting with black's move leaves the first white cell empty
+        self.offset % 2
+    }
+    fn visible_rows(&self) -> usize {
+        (self.rect.3 / ROW_HEIGHT).max(1.0) as usize
+    }
+    pub fn contains(&self, mousepos: (f32, f32)) -> bool {
+        let (x, y) = mousepos;
+        (x > self.rect.0) && (x < self.rect.0 + self.rect.2) && (y > self.rect.1) && (y < self.rect.1 + self.rect.3)
+    }
+    pub fn scroll_by(&mut self, delta: isize, n_moves: usize) {
+        let n_rows = (n_moves + self.pad() + 1) / 2;
+        let max_scroll = n_rows.saturating_sub(self.visible_rows());
+        self.scroll = (self.scroll as isize + delta).clamp(0, max_scroll as isize) as usize;
+    }
+    pub fn follow(&mut self, ply: usize) {
+        // keeps the row of the given ply on screen
+        if ply == 0 {
+            return;
+        }
+        let row = (ply - 1 + self.pad()) / 2;
+        if row < self.scroll {
+            self.scroll = row;
+        }
+        else if row >= self.scroll + self.visible_rows() {
+            self.scroll = row + 1 - self.visible_rows();
+        }
+    }
+    pub fn ply_at(&self, mousepos: (f32, f32), n_moves: usize) -> Option<usize> {
+        if !self.contains(mousepos) {
+            return None;
+        }
+        let row = ((mousepos.1 - self.rect.1) / ROW_HEIGHT) as usize + self.scroll;
+        let x = mousepos.0 - self.rect.0 - NUMBER_WIDTH;
+        if x < 0.0 {
+            return None;
+        }
+        let cell = row * 2 + (if x < MOVE_WIDTH { 0 } else { 1 });
+        if (cell < self.pad()) || (cell + 1 - self.pad() > n_moves) {
+            return None;
+        }
+        return Some(cell + 1 - self.pad());
+    }
+    pub fn draw(&self, sans: &[String], current: usize, font: Font, color: Color, highlight: Color) {
+        let (x, y, _, _) = self.rect;
+        let mut cells: Vec<String> = Vec::new();
+        if self.pad() == 1 {
+            cells.push("...".to_string());
+        }
+        cells.extend(sans.iter().cloned());
+        for (i, pair) in cells.chunks(2).enumerate().skip(self.scroll).take(self.visible_rows()) {
+            let row_y = y + (i - self.scroll) as f32 * ROW_HEIGHT;
+            draw_text_ex(
+                &format!("{}.", self.offset / 2 + i + 1),
+                x,
+                row_y + ROW_HEIGHT - 4.0,
+                TextParams{font: font, font_size: 16u16, color: color, ..Default::default()}
+            );
+            for (j, san) in pair.iter().enumerate() {
+                let move_x = x + NUMBER_WIDTH + j as f32 * MOVE_WIDTH;
+                if (i * 2 + j + 1 == current + self.pad()) && (i * 2 + j >= self.pad()) {
+                    draw_rectangle(move_x - 3.0, row_y, MOVE_WIDTH - 4.0, ROW_HEIGHT, highlight);
+                }
+                draw_text_ex(
+                    san,
+                    move_x,
+                    row_y + ROW_HEIGHT - 4.0,
+                    TextParams{font: font, font_size: 16u16, color: color, ..Default::default()}
+                );
+            }
+        }
+    }
+}
\ No newline at end of file
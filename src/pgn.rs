@@ -0,0 +1,304 @@
+use super::fen_counters;
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+
+#[derive(Clone, Default)]
+pub struct PgnMove {
+    pub san: String,
+    pub nags: Vec<u8>,
+    pub comment: String
+}
+
+#[derive(Clone, Default)]
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub comment: String,
+    pub moves: Vec<PgnMove>,
+    pub result: String
+}
+
+impl PgnGame {
+    pub fn new() -> Self {
+        Self {
+            result: "*".to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    }
+    pub fn set_tag(&mut self, name: &str, value: &str) {
+        match self.tags.iter_mut().find(|(key, _)| key == name) {
+            Some(tag) => tag.1 = value.to_string(),
+            None => self.tags.push((name.to_string(), value.to_string()))
+        }
+    }
+    pub fn first_ply(&self) -> usize {
+        // games set up from a position start at the move number and side in their fen
+        let fen = match self.tag("FEN") {
+            Some(fen) => fen,
+            None => return 0
+        };
+        let (_, fullmove) = fen_counters(fen);
+        let black = fen.split_whitespace().nth(1) == Some("b");
+        return (fullmove - 1) * 2 + black as usize;
+    }
+    pub fn to_text(&self) -> String {
+        let mut text = String::new();
+        for (name, value) in self.tags.iter() {
+            text.push_str(&format!("[{} \"{}\"]\n", name, value.replace('\\', "\\\\").replace('"', "\\\"")));
+        }
+        text.push('\n');
+
+        let mut tokens = Vec::new();
+        if !self.comment.is_empty() {
+            tokens.push(format!("{{{}}}", self.comment));
+        }
+        let mut needs_number = true;
+        let first_ply = self.first_ply();
+        for (i, pgn_move) in self.moves.iter().enumerate() {
+            let ply = first_ply + i;
+            if ply % 2 == 0 {
+                tokens.push(format!("{}.", ply / 2 + 1));
+            }
+            else if needs_number {
+                // black's move needs its own number after a comment or at the start
+                tokens.push(format!("{}...", ply / 2 + 1));
+            }
+            tokens.push(pgn_move.san.clone());
+            for nag in pgn_move.nags.iter() {
+                tokens.push(format!("${}", nag));
+            }
+            needs_number = !pgn_move.comment.is_empty();
+            if needs_number {
+                tokens.push(format!("{{{}}}", pgn_move.comment));
+            }
+        }
+        tokens.push(self.result.clone());
+
+        let mut line = String::new();
+        for token in tokens.iter() {
+            if !line.is_empty() && (line.len() + token.len() + 1 > 80) {
+                text.push_str(&line);
+                text.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(token);
+        }
+        text.push_str(&line);
+        text.push('\n');
+        return text;
+    }
+}
+
+
+pub fn nag_glyph(nag: u8) -> Option<&'static str> {
+    // the move assessments that have a common symbol
+    match nag {
+        1 => Some("!"),
+        2 => Some("?"),
+        3 => Some("!!"),
+        4 => Some("??"),
+        5 => Some("!?"),
+        6 => Some("?!"),
+        _ => None
+    }
+}
+
+
+pub fn parse_pgn(text: &str) -> Vec<PgnGame> {
+    let mut games = Vec::new();
+    let mut game = PgnGame::new();
+    let mut in_movetext = false;
+    let mut chars = text.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            '[' if !in_movetext || game.moves.is_empty() => {
+                chars.next();
+                let line: String = chars.by_ref().take_while(|c| *c != '\n').collect();
+                if let Some((name, value)) = parse_tag(&line) {
+                    game.tags.push((name, value));
+                }
+            },
+            '[' => {
+                // a tag section after moves without a result starts a new game
+                games.push(game);
+                game = PgnGame::new();
+                in_movetext = false;
+            },
+            '{' => {
+                chars.next();
+                let comment: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let comment = comment.split_whitespace().collect::<Vec<&str>>().join(" ");
+                let target = match game.moves.last_mut() {
+                    Some(last) => &mut last.comment,
+                    None => &mut game.comment
+                };
+                if !target.is_empty() {
+                    target.push(' ');
+                }
+                target.push_str(&comment);
+            },
+            ';' => {
+                chars.by_ref().take_while(|c| *c != '\n').for_each(drop);
+            },
+            '(' => {
+                // variations are skipped
+                let mut depth = 0;
+                while let Some(c) = chars.next() {
+                    match c {
+                        '(' => depth += 1,
+                        ')' => depth -= 1,
+                        '{' => chars.by_ref().take_while(|c| *c != '}').for_each(drop),
+                        _ => ()
+                    }
+                    if depth == 0 {
+                        break;
+                    }
+                }
+            },
+            ')' | '}' => {
+                // a closer without its opener is skipped
+                chars.next();
+            },
+            c if c.is_whitespace() => { chars.next(); },
+            _ => {
+                let mut token = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || "{}();[".contains(c) {
+                        break;
+                    }
+                    token.push(c);
+                    chars.next();
+                }
+                in_movetext = true;
+                if RESULTS.contains(&token.as_str()) {
+                    game.result = token;
+                    games.push(game);
+                    game = PgnGame::new();
+                    in_movetext = false;
+                    continue;
+                }
+                if let Some(nag) = token.strip_prefix('$') {
+                    if let (Some(last), Ok(nag)) = (game.moves.last_mut(), nag.parse()) {
+                        last.nags.push(nag);
+                    }
+                    continue;
+                }
+                // move numbers may be glued to the move, as in "1.e4", but castling written as "0-0" starts with a digit too
+                let rest = token.trim_start_matches(|c: char| c.is_ascii_digit());
+                let san = if rest.starts_with('.') { rest.trim_start_matches('.') } else if rest.is_empty() { "" } else { token.as_str() };
+                if !san.is_empty() {
+                    game.moves.push(PgnMove {
+                        san: san.to_string(),
+                        ..Default::default()
+                    });
+                }
+            }
+        }
+    }
+    if !game.moves.is_empty() || !game.tags.is_empty() {
+        games.push(game);
+    }
+    return games;
+}
+
+fn parse_tag(line: &str) -> Option<(String, String)> {
+    let line = line.trim().trim_end_matches(']');
+    let (name, value) = line.split_once(' ')?;
+    let value = value.trim().strip_prefix('"')?.strip_suffix('"')?;
+    return Some((name.to_string(), value.replace("\\\"", "\"").replace("\\\\", "\\")));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::{Board, PieceType, Team};
+
+    fn sans(game: &PgnGame) -> Vec<&str> {
+        game.moves.iter().map(|pgn_move| pgn_move.san.as_str()).collect()
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        let text = "[Event \"Test \\\"quoted\\\"\"]\n[White \"A\"]\n\n{Opening comment} 1. e4 $1 e5 {a comment} 2. Nf3 Nc6 $6 1-0\n";
+        let game = &parse_pgn(text)[0];
+        assert_eq!(game.tag("Event"), Some("Test \"quoted\""));
+        assert_eq!(game.comment, "Opening comment");
+        assert_eq!(sans(game), ["e4", "e5", "Nf3", "Nc6"]);
+        assert_eq!(game.moves[0].nags, [1]);
+        assert_eq!(game.moves[1].comment, "a comment");
+        assert_eq!(game.result, "1-0");
+
+        let again = &parse_pgn(&game.to_text())[0];
+        assert_eq!(again.tags, game.tags);
+        assert_eq!(again.comment, game.comment);
+        assert_eq!(sans(again), sans(game));
+        assert_eq!(again.moves[3].nags, [6]);
+        assert_eq!(again.moves[1].comment, "a comment");
+        assert_eq!(again.result, game.result);
+    }
+
+    #[test]
+    fn splits_games_and_skips_variations() {
+        let text = "[Round \"1\"]\n\n1. d4 (1. e4 e5 (1... c5)) d5 ; rest of line\n2. c4 1/2-1/2\n\n[Round \"2\"]\n\n1.e4 1...c5 *\n";
+        let games = parse_pgn(text);
+        assert_eq!(games.len(), 2);
+        assert_eq!(sans(&games[0]), ["d4", "d5", "c4"]);
+        assert_eq!(games[0].result, "1/2-1/2");
+        assert_eq!(sans(&games[1]), ["e4", "c5"]);
+        assert_eq!(games[1].result, "*");
+    }
+
+    #[test]
+    fn skips_stray_closers() {
+        let games = parse_pgn("1. e4 ) e5 } 2. Nf3 *");
+        assert_eq!(sans(&games[0]), ["e4", "e5", "Nf3"]);
+    }
+
+    #[test]
+    fn keeps_castling_written_with_zeros() {
+        let games = parse_pgn("1. 0-0 0-0-0 2.O-O *");
+        assert_eq!(sans(&games[0]), ["0-0", "0-0-0", "O-O"]);
+    }
+
+    #[test]
+    fn numbers_games_set_up_from_black_to_move() {
+        let mut game = PgnGame::new();
+        game.set_tag("FEN", "4k3/8/8/8/8/8/4P3/4K3 b - - 0 12");
+        game.moves.push(PgnMove { san: "Kd7".to_string(), ..Default::default() });
+        game.moves.push(PgnMove { san: "e4".to_string(), ..Default::default() });
+        assert_eq!(game.first_ply(), 23);
+        assert!(game.to_text().contains("12... Kd7 13. e4 *"));
+    }
+
+    #[test]
+    fn finds_and_writes_san() {
+        let (mut board, side) = (Board::new(), Team::White);
+        let (piece, action) = board.find_san(side, "Nf3").unwrap();
+        assert!(piece.ptype == PieceType::Knight);
+        assert_eq!(board.to_san(piece, &action), "Nf3");
+        assert!(board.find_san(side, "Nf4").is_none());
+
+        // both rooks reach d1 and a3, so the file or the rank is needed
+        let (mut board, side) = Board::from_fen("4k3/8/8/R7/8/8/4K3/R6R w - - 0 1").unwrap();
+        for san in ["Rad1", "Rhd1", "R1a3", "R5a3"] {
+            let (piece, action) = board.find_san(side, san).unwrap();
+            assert_eq!(board.to_san(piece, &action), san);
+        }
+    }
+
+    #[test]
+    fn replays_underpromotions() {
+        let (mut board, side) = Board::from_fen("8/4P3/8/8/8/8/k7/6K1 w - - 0 1").unwrap();
+        let (mut piece, action) = board.find_san(side, "e8=N").unwrap();
+        assert_eq!(board.to_san(piece, &action), "e8=N");
+        board.execute_move(&mut piece, action.copy(), false, false);
+        assert!(board.tiles[0][4].present_piece.map_or(false, |piece| piece.ptype == PieceType::Knight));
+        assert!(board.find_san(side, "e8=K").is_none());
+    }
+}
@@ -24,6 +24,28 @@ impl PieceType {
             Self::None => "".to_string()
         }
     }
+    pub fn as_char(&self) -> char {
+        match *self {
+            Self::Pawn => 'P',
+            Self::Knight => 'N',
+            Self::Bishop => 'B',
+            Self::Rook => 'R',
+            Self::Queen => 'Q',
+            Self::King => 'K',
+            Self::None => ' '
+        }
+    }
+    pub fn from_char(c: char) -> Option<Self> {
+        match c.to_ascii_uppercase() {
+            'P' => Some(Self::Pawn),
+            'N' => Some(Self::Knight),
+            'B' => Some(Self::Bishop),
+            'R' => Some(Self::Rook),
+            'Q' => Some(Self::Queen),
+            'K' => Some(Self::King),
+            _ => None
+        }
+    }
     pub fn as_int(&self) -> i32 {
         match *self {
             Self::Pawn => 1,
@@ -105,9 +127,6 @@ impl Piece {
     // pub fn set_texture(&mut self, size: usize) {
     //     self.texture_path = format!("../assets/images/imgs-{}px/{}_{}.png", size, self.name, self.color.as_string());
     // }
-    pub fn texture_path(&self, size: usize) -> String {
-        format!("assets/images/imgs-{}px/{}_{}.png", size, self.color.as_string(), self.ptype.as_string())
-    }
     pub fn make_moved(&mut self) {
         self.has_moved = true;
     }
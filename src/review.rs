@@ -0,0 +1,237 @@
+use std::fs;
+use std::path::Path;
+use super::{Algorithms, Board, Team, PgnGame, parse_pgn, SEARCH_DEPTH};
+
+const PGN_DIR: &str = "assets/pgns";
+const REVIEW_DIR: &str = "saves/reviews";
+// centipawn losses from which a move counts as good, an inaccuracy, a mistake and a blunder
+const LOSS_THRESHOLDS: [f32; 4] = [10.0, 50.0, 100.0, 300.0];
+// mates and lopsided positions are all treated as ten pawns
+const EVAL_CAP: f32 = 1000.0;
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum MoveClass {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder
+}
+
+impl MoveClass {
+    pub fn from_loss(loss: f32) -> Self {
+        match LOSS_THRESHOLDS.iter().filter(|threshold| loss >= **threshold).count() {
+            0 => Self::Best,
+            1 => Self::Good,
+            2 => Self::Inaccuracy,
+            3 => Self::Mistake,
+            _ => Self::Blunder
+        }
+    }
+    pub fn as_string(&self) -> String {
+        match *self {
+            Self::Best => "Best".to_string(),
+            Self::Good => "Good".to_string(),
+            Self::Inaccuracy => "Inaccuracy".to_string(),
+            Self::Mistake => "Mistake".to_string(),
+            Self::Blunder => "Blunder".to_string()
+        }
+    }
+    pub fn nag(&self) -> Option<u8> {
+        match *self {
+            Self::Inaccuracy => Some(6),
+            Self::Mistake => Some(2),
+            Self::Blunder => Some(4),
+            _ => None
+        }
+    }
+}
+
+
+pub struct MoveReview {
+    pub player: Team,
+    pub class: MoveClass,
+    // centipawns, evaluations from white's point of view
+    pub loss: f32,
+    pub eval_before: f32,
+    pub eval_after: f32,
+    pub best: Option<String>,
+    pub accuracy: f32
+}
+
+pub struct GameReview {
+    pub moves: Vec<MoveReview>
+}
+
+impl GameReview {
+    pub fn accuracy(&self, player: Team) -> f32 {
+        let moves: Vec<f32> = self.moves.iter().filter(|review| review.player == player).map(|review| review.accuracy).collect();
+        if moves.is_empty() {
+            return 100.0;
+        }
+        return moves.iter().sum::<f32>() / moves.len() as f32;
+    }
+    pub fn average_loss(&self, player: Team) -> f32 {
+        let losses: Vec<f32> = self.moves.iter().filter(|review| review.player == player).map(|review| review.loss).collect();
+        if losses.is_empty() {
+            return 0.0;
+        }
+        return losses.iter().sum::<f32>() / losses.len() as f32;
+    }
+    pub fn count(&self, player: Team, class: MoveClass) -> usize {
+        self.moves.iter().filter(|review| (review.player == player) && (review.class == class)).count()
+    }
+    pub fn summary(&self, player: Team) -> String {
+        format!(
+            "{} {:.1}%, average loss {:.0} ({} inaccuracies, {} mistakes, {} blunders)",
+            player.as_string(),
+            self.accuracy(player),
+            self.average_loss(player),
+            self.count(player, MoveClass::Inaccuracy),
+            self.count(player, MoveClass::Mistake),
+            self.count(player, MoveClass::Blunder)
+        )
+    }
+}
+
+
+fn to_centipawns(engine: &Algorithms, evaluation: f32) -> f32 {
+    engine.eval.to_centipawns(evaluation).clamp(-EVAL_CAP, EVAL_CAP)
+}
+
+fn win_percent(centipawns: f32) -> f32 {
+    // the same logistic curve lichess uses for its accuracy figures
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.00368208 * centipawns).exp()) - 1.0)
+}
+
+fn move_accuracy(before: f32, after: f32) -> f32 {
+    // both in centipawns from the mover's point of view
+    let drop = (win_percent(before) - win_percent(after)).max(0.0);
+    (103.1668 * (-0.04354 * drop).exp() - 3.1669).clamp(0.0, 100.0)
+}
+
+fn evaluate_position(engine: &mut Algorithms, board: &Board, side: Team, depth: isize) -> (f32, Option<String>) {
+    // centipawns for the side to move and the engine's choice in san
+    engine.set_perspective(side);
+    let (evaluation, (piece, action)) = engine.search_multi(board.copy(), depth);
+    match (piece, action) {
+        (Some(piece), Some(action)) => (to_centipawns(engine, evaluation), Some(board.to_san(piece, &action))),
+        _ => {
+            let in_check = board.get_pins_and_checks(side).0;
+            (if in_check { -EVAL_CAP } else { 0.0 }, None)
+        }
+    }
+}
+
+pub fn review_game(pgn: &PgnGame, depth: isize) -> Result<(PgnGame, GameReview), String> {
+    let (mut board, mut side) = match pgn.tag("FEN") {
+        Some(fen) => Board::from_fen(fen)?,
+        None => (Board::new(), Team::White)
+    };
+    // one engine per side, switching perspective would clear the transposition table every move
+    let mut engines = [Algorithms::new(Team::White), Algorithms::new(Team::Black)];
+    let engine_idx = |side: Team| if side == Team::White { 0 } else { 1 };
+    let mut annotated = pgn.clone();
+    let mut moves = Vec::new();
+    let (mut eval, mut best) = evaluate_position(&mut engines[engine_idx(side)], &board, side, depth);
+    for (i, pgn_move) in pgn.moves.iter().enumerate() {
+        let (mut piece, action) = match board.find_san(side, &pgn_move.san) {
+            Some(found) => found,
+            None => return Err(format!("illegal move {}{} {}", i / 2 + 1, if i % 2 == 0 { "." } else { "..." }, pgn_move.san))
+        };
+        let played = board.to_san(piece, &action);
+        board.execute_move(&mut piece, action.copy(), false, false);
+        board.set_en_passant(&mut piece, action);
+        let (reply_eval, reply_best) = evaluate_position(&mut engines[engine_idx(side.other())], &board, side.other(), depth);
+
+        // playing the engine's move costs nothing, whatever the two searches say
+        let loss = if best.as_deref() == Some(played.as_str()) { 0.0 } else { (eval + reply_eval).max(0.0) };
+        let class = MoveClass::from_loss(loss);
+        let white_view = |centipawns: f32| if side == Team::White { centipawns } else { -centipawns };
+        let review = MoveReview {
+            player: side,
+            class: class,
+            loss: loss,
+            eval_before: white_view(eval),
+            eval_after: white_view(-reply_eval),
+            best: best.clone(),
+            accuracy: move_accuracy(eval, -reply_eval)
+        };
+
+        let annotated_move = &mut annotated.moves[i];
+        annotated_move.nags.retain(|nag| !(1..=6).contains(nag));
+        if let Some(nag) = class.nag() {
+            annotated_move.nags.push(nag);
+            let mut text = format!("{} ({:+.2}).", class.as_string(), review.eval_after / 100.0);
+            if let Some(best) = &review.best {
+                text.push_str(&format!(" {} was best ({:+.2}).", best, review.eval_before / 100.0));
+            }
+            annotated_move.comment = if annotated_move.comment.is_empty() { text } else { format!("{} {}", annotated_move.comment, text) };
+        }
+        moves.push(review);
+        (eval, best) = (reply_eval, reply_best);
+        side = side.other();
+    }
+
+    let review = GameReview {
+        moves: moves
+    };
+    let summary = format!("{}, {}", review.summary(Team::White), review.summary(Team::Black));
+    annotated.comment = if annotated.comment.is_empty() { summary } else { format!("{} {}", summary, annotated.comment) };
+    annotated.set_tag("Annotator", &format!("Chess, depth {}", depth));
+    return Ok((annotated, review));
+}
+
+
+pub fn review_cli(args: &[String]) {
+    // chess --review <file> [--depth n] [--out path]
+    let flag_value = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|idx| args.get(idx + 1)).cloned();
+    let input = match flag_value("--review") {
+        Some(input) => input,
+        None => {
+            eprintln!("usage: chess --review <pgn file> [--depth n] [--out path]");
+            return;
+        }
+    };
+    let depth = flag_value("--depth").and_then(|depth| depth.parse().ok()).unwrap_or(SEARCH_DEPTH);
+    // bare names are looked up in the bundled collections
+    let path = if Path::new(&input).exists() { input.clone() } else { format!("{}/{}", PGN_DIR, input) };
+    let stem = Path::new(&path).file_stem().map_or("review".to_string(), |stem| stem.to_string_lossy().to_string());
+    let out = flag_value("--out").unwrap_or(format!("{}/{}.pgn", REVIEW_DIR, stem));
+
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(err) => {
+            eprintln!("could not read {}: {}", path, err);
+            return;
+        }
+    };
+    let games = parse_pgn(&text);
+    let mut output = String::new();
+    for (i, pgn) in games.iter().enumerate() {
+        let players = format!("{} - {}", pgn.tag("White").unwrap_or("?"), pgn.tag("Black").unwrap_or("?"));
+        match review_game(pgn, depth) {
+            Ok((annotated, review)) => {
+                println!("[{}/{}] {}: {}, {}", i + 1, games.len(), players, review.summary(Team::White), review.summary(Team::Black));
+                output.push_str(&annotated.to_text());
+            },
+            Err(err) => {
+                // the game is kept as it was, so the output still holds the whole file
+                println!("[{}/{}] {}: skipped, {}", i + 1, games.len(), players, err);
+                output.push_str(&pgn.to_text());
+            }
+        }
+        output.push('\n');
+    }
+    if let Some(dir) = Path::new(&out).parent() {
+        if let Err(err) = fs::create_dir_all(dir) {
+            eprintln!("could not create {}: {}", dir.display(), err);
+            return;
+        }
+    }
+    match fs::write(&out, output) {
+        Ok(()) => println!("wrote {}", out),
+        Err(err) => eprintln!("could not write {}: {}", out, err)
+    }
+}
\ No newline at end of file
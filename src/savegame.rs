@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use super::{Team, EndReason};
+
+const SAVE_DIR: &str = "saves";
+
+
+#[derive(Serialize, Deserialize)]
+pub struct SaveMeta {
+    pub use_ai: bool,
+    pub human_side: Team,
+    pub analysis_mode: bool,
+    pub search_depth: isize,
+    pub multi_pv: usize,
+    // base, increment and delay in milliseconds
+    pub time_control: Option<[u64; 3]>,
+    pub clocks: [u64; 2],
+    pub cursor: usize,
+    pub winner: Team,
+    pub end_reason: Option<EndReason>
+}
+
+impl SaveMeta {
+    pub fn path_for(pgn_path: &str) -> String {
+        // the metadata sits next to the pgn it belongs to
+        Path::new(pgn_path).with_extension("toml").to_string_lossy().to_string()
+    }
+    pub fn load(pgn_path: &str) -> Option<Self> {
+        let path = Self::path_for(pgn_path);
+        let text = fs::read_to_string(&path).ok()?;
+        match toml::from_str(&text) {
+            Ok(meta) => Some(meta),
+            Err(err) => {
+                eprintln!("ignoring {}: {}", path, err);
+                None
+            }
+        }
+    }
+    pub fn save(&self, pgn_path: &str) -> Result<(), String> {
+        let path = Self::path_for(pgn_path);
+        let text = toml::to_string(self).map_err(|err| format!("could not serialize {}: {}", path, err))?;
+        fs::write(&path, text).map_err(|err| format!("could not write {}: {}", path, err))
+    }
+}
+
+
+pub fn saved_games() -> Vec<String> {
+    // newest first, so the game just played is at the top
+    let mut games: Vec<(std::time::SystemTime, String)> = Vec::new();
+    if let Ok(entries) = fs::read_dir(SAVE_DIR) {
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.extension().map_or(false, |ext| ext == "pgn") {
+                let modified = entry.metadata().and_then(|meta| meta.modified()).unwrap_or(std::time::UNIX_EPOCH);
+                games.push((modified, path.to_string_lossy().to_string()));
+            }
+        }
+    }
+    games.sort_by(|a, b| b.0.cmp(&a.0));
+    return games.into_iter().map(|(_, path)| path).collect();
+}
\ No newline at end of file
@@ -0,0 +1,86 @@
+use std::thread::{self, JoinHandle};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use super::{Algorithms, Board, Piece, Move, SearchStats};
+
+
+pub type SearchResult = (f32, (Option<Piece>, Option<Move>));
+
+#[derive(Clone)]
+pub struct PvLine {
+    pub evaluation: f32,
+    pub moves: Vec<(Piece, Move)>
+}
+
+#[derive(Clone, Default)]
+pub struct SearchProgress {
+    pub depth: isize,
+    pub evaluation: f32,
+    pub best_move: Option<Move>,
+    pub lines: Vec<PvLine>
+}
+
+pub struct SearchOutcome {
+    pub result: SearchResult,
+    pub stats: SearchStats,
+    pub lines: Vec<PvLine>
+}
+
+pub struct BackgroundSearch {
+    stop: Arc<AtomicBool>,
+    progress: Arc<Mutex<SearchProgress>>,
+    handle: Option<JoinHandle<SearchOutcome>>
+}
+
+impl BackgroundSearch {
+    pub fn start(algorithms: &Algorithms, state: Board, depth: isize) -> Self {
+        let mut engine = algorithms.fork();
+        let stop = engine.stop.clone();
+        let progress = Arc::new(Mutex::new(SearchProgress::default()));
+        let thread_progress = progress.clone();
+        let handle = thread::spawn(move || {
+            let result = engine.search(state, depth, &thread_progress);
+            SearchOutcome {
+                result: result,
+                stats: engine.stats,
+                lines: engine.lines
+            }
+        });
+        Self {
+            stop: stop,
+            progress: progress,
+            handle: Some(handle)
+        }
+    }
+    pub fn progress(&self) -> SearchProgress {
+        self.progress.lock().unwrap().clone()
+    }
+    pub fn is_finished(&self) -> bool {
+        self.handle.as_ref().map_or(true, |handle| handle.is_finished())
+    }
+    pub fn wait(mut self) -> SearchOutcome {
+        self.handle.take().unwrap().join().unwrap()
+    }
+    pub fn stop(self) -> SearchOutcome {
+        // the finished iterations still give a move
+        self.stop.store(true, Ordering::Relaxed);
+        self.wait()
+    }
+    pub fn cancel(mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        let _ = self.handle.take().unwrap().join();
+    }
+}
+
+impl Drop for BackgroundSearch {
+    fn drop(&mut self) {
+        // a search that is dropped without being waited on is no longer needed
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+
+pub struct Ponder {
+    pub expected: Move,
+    pub search: BackgroundSearch
+}
\ No newline at end of file
@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use super::SEARCH_DEPTH;
+
+
+const SETTINGS_FILE: &str = "saves/settings.toml";
+
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(default)]
+pub struct Settings {
+    pub theme: String,
+    pub piece_set: String,
+    pub sound: bool,
+    pub volume: f32,
+    pub coordinates: bool,
+    pub animation_ms: u64,
+    pub search_depth: isize,
+    pub ponder: bool,
+    pub multi_pv: usize,
+    pub resign_threshold: f32,
+    pub resign_moves: usize
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            theme: "brown".to_string(),
+            piece_set: "default".to_string(),
+            sound: true,
+            volume: 1.0,
+            coordinates: true,
+            animation_ms: 150,
+            search_depth: SEARCH_DEPTH,
+            ponder: true,
+            multi_pv: 1,
+            resign_threshold: 6.0,
+            resign_moves: 3
+        }
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        // a missing file just means nothing has been changed yet
+        let text = match fs::read_to_string(SETTINGS_FILE) {
+            Ok(text) => text,
+            Err(_) => return Self::default()
+        };
+        match toml::from_str(&text) {
+            Ok(settings) => settings,
+            Err(err) => {
+                eprintln!("ignoring {}: {}", SETTINGS_FILE, err);
+                Self::default()
+            }
+        }
+    }
+    pub fn save(&self) {
+        let text = match toml::to_string(self) {
+            Ok(text) => text,
+            Err(err) => {
+                eprintln!("could not serialize settings: {}", err);
+                return;
+            }
+        };
+        if let Some(dir) = Path::new(SETTINGS_FILE).parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Err(err) = fs::write(SETTINGS_FILE, text) {
+            eprintln!("could not write {}: {}", SETTINGS_FILE, err);
+        }
+    }
+}
\ No newline at end of file
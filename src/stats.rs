@@ -0,0 +1,144 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+
+pub struct SearchCounters {
+    nodes: AtomicUsize,
+    evaluated_states: AtomicUsize,
+    pruned_states: AtomicUsize,
+    first_move_cutoffs: AtomicUsize,
+    expanded_nodes: AtomicUsize,
+    searched_children: AtomicUsize,
+    tt_probes: AtomicUsize,
+    tt_hits: AtomicUsize,
+    seldepth: AtomicUsize,
+}
+
+impl SearchCounters {
+    pub fn new() -> Self {
+        Self {
+            nodes: AtomicUsize::new(0),
+            evaluated_states: AtomicUsize::new(0),
+            pruned_states: AtomicUsize::new(0),
+            first_move_cutoffs: AtomicUsize::new(0),
+            expanded_nodes: AtomicUsize::new(0),
+            searched_children: AtomicUsize::new(0),
+            tt_probes: AtomicUsize::new(0),
+            tt_hits: AtomicUsize::new(0),
+            seldepth: AtomicUsize::new(0),
+        }
+    }
+    pub fn reset(&self) {
+        for counter in [
+            &self.nodes, &self.evaluated_states, &self.pruned_states, &self.first_move_cutoffs,
+            &self.expanded_nodes, &self.searched_children, &self.tt_probes, &self.tt_hits, &self.seldepth
+        ] {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+    pub fn add_node(&self, ply: usize) {
+        self.nodes.fetch_add(1, Ordering::Relaxed);
+        self.seldepth.fetch_max(ply, Ordering::Relaxed);
+    }
+    pub fn add_evaluated(&self) {
+        self.evaluated_states.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn add_cutoff(&self, move_number: usize) {
+        self.pruned_states.fetch_add(1, Ordering::Relaxed);
+        if move_number == 0 {
+            self.first_move_cutoffs.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    pub fn add_expanded(&self, children: usize) {
+        self.expanded_nodes.fetch_add(1, Ordering::Relaxed);
+        self.searched_children.fetch_add(children, Ordering::Relaxed);
+    }
+    pub fn add_tt_probe(&self, hit: bool) {
+        self.tt_probes.fetch_add(1, Ordering::Relaxed);
+        if hit {
+            self.tt_hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+    pub fn snapshot(&self, depth: isize, elapsed_ms: u128) -> SearchStats {
+        SearchStats {
+            depth: depth,
+            seldepth: self.seldepth.load(Ordering::Relaxed),
+            nodes: self.nodes.load(Ordering::Relaxed),
+            evaluated_states: self.evaluated_states.load(Ordering::Relaxed),
+            pruned_states: self.pruned_states.load(Ordering::Relaxed),
+            first_move_cutoffs: self.first_move_cutoffs.load(Ordering::Relaxed),
+            expanded_nodes: self.expanded_nodes.load(Ordering::Relaxed),
+            searched_children: self.searched_children.load(Ordering::Relaxed),
+            tt_probes: self.tt_probes.load(Ordering::Relaxed),
+            tt_hits: self.tt_hits.load(Ordering::Relaxed),
+            elapsed_ms: elapsed_ms,
+        }
+    }
+}
+
+
+
+#[derive(Clone, Copy, Default)]
+pub struct SearchStats {
+    pub depth: isize,
+    pub seldepth: usize,
+    pub nodes: usize,
+    pub evaluated_states: usize,
+    pub pruned_states: usize,
+    pub first_move_cutoffs: usize,
+    pub expanded_nodes: usize,
+    pub searched_children: usize,
+    pub tt_probes: usize,
+    pub tt_hits: usize,
+    pub elapsed_ms: u128,
+}
+
+impl SearchStats {
+    pub fn nps(&self) -> usize {
+        (self.nodes as u128 * 1000 / self.elapsed_ms.max(1)) as usize
+    }
+    pub fn tt_hit_rate(&self) -> f32 {
+        if self.tt_probes == 0 {
+            return 0.0;
+        }
+        self.tt_hits as f32 / self.tt_probes as f32
+    }
+    pub fn branching_factor(&self) -> f32 {
+        if self.expanded_nodes == 0 {
+            return 0.0;
+        }
+        self.searched_children as f32 / self.expanded_nodes as f32
+    }
+    pub fn first_move_cutoff_rate(&self) -> f32 {
+        if self.pruned_states == 0 {
+            return 0.0;
+        }
+        self.first_move_cutoffs as f32 / self.pruned_states as f32
+    }
+    pub fn uci_info(&self, multi_pv: usize, centipawns: f32, pv: &str) -> String {
+        let mut info = format!(
+            "info depth {} seldepth {} multipv {} nodes {} nps {} time {} score cp {}",
+            self.depth, self.seldepth, multi_pv, self.nodes, self.nps(), self.elapsed_ms, centipawns.round() as i32
+        );
+        if !pv.is_empty() {
+            info.push_str(&format!(" pv {}", pv));
+        }
+        return info;
+    }
+    pub fn uci_extra_info(&self) -> String {
+        format!(
+            "info string tthits {:.1}% branching {:.2} firstcut {:.1}% evaluated {} pruned {}",
+            self.tt_hit_rate() * 100.0, self.branching_factor(), self.first_move_cutoff_rate() * 100.0, self.evaluated_states, self.pruned_states
+        )
+    }
+    pub fn as_lines(&self) -> Vec<String> {
+        Vec::from([
+            format!("depth {}/{}", self.depth, self.seldepth),
+            format!("nodes {}", self.nodes),
+            format!("nps {}", self.nps()),
+            format!("time {}ms", self.elapsed_ms),
+            format!("tt hits {:.1}%", self.tt_hit_rate() * 100.0),
+            format!("branching {:.2}", self.branching_factor()),
+            format!("1st cut {:.1}%", self.first_move_cutoff_rate() * 100.0),
+        ])
+    }
+}
\ No newline at end of file
@@ -1,5 +1,8 @@
 
-#[derive(Eq, Hash, PartialEq, Clone, Copy)]
+use serde::{Serialize, Deserialize};
+
+
+#[derive(Eq, Hash, PartialEq, Clone, Copy, Serialize, Deserialize)]
 pub enum Team {
     White,
     Black,
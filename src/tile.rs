@@ -1,12 +1,15 @@
 use std::collections::HashMap;
 #[path = "piece.rs"] mod piece;
 pub use piece::*;
+use super::square_name;
 
 
 #[derive(Clone)]
 pub struct Move {
     pub initial: Tile,
     pub end: Tile,
+    // what a pawn reaching the last rank turns into
+    pub promotion: PieceType,
 }
 
 impl std::fmt::Debug for Move {
@@ -29,14 +32,24 @@ impl Move {
         Self {
             initial: initial,
             end: end,
+            promotion: PieceType::Queen,
         }
     }
     pub fn copy(&self) -> Move {
         return Move {
             initial: self.initial.copy(),
-            end: self.end.copy()
+            end: self.end.copy(),
+            promotion: self.promotion
         };
     }
+    pub fn with_promotion(&self, ptype: PieceType) -> Move {
+        let mut action = self.copy();
+        action.promotion = ptype;
+        return action;
+    }
+    pub fn to_uci(&self) -> String {
+        format!("{}{}", square_name(self.initial.row, self.initial.col), square_name(self.end.row, self.end.col))
+    }
 }
 
 #[derive(PartialEq, Clone, Copy)]
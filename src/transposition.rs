@@ -0,0 +1,130 @@
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicU8, Ordering};
+use super::{Board, Piece, PieceType, Move, Team};
+
+// white king side, white queen side, black king side, black queen side
+const CASTLING_KEYS: [u64; 4] = [0x493cd01609de8895, 0xc33f4584b23bc1d8, 0xf59ba79924d8cea5, 0x5eb0452176688387];
+const EN_PASSANT_KEYS: [u64; 8] = [
+    0xb2ccdfa7abf10ac2, 0x18a61865cafedacf, 0x994b7a5674043590, 0x9be6a8ea7f8ec4c2,
+    0x839aa6b004e77af5, 0x939d61dc6eb108e2, 0x9fdfe1685f53f26c, 0xf275418cda2f966e
+];
+// a power of two, so the low bits of the key pick the slot
+const TT_SIZE: usize = 1 << 16;
+
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Bound {
+    Exact,
+    Lower,
+    Upper
+}
+
+#[derive(Clone)]
+pub struct TTEntry {
+    pub depth: isize,
+    pub eval: f32,
+    pub bound: Bound,
+    pub best_move: (Option<Piece>, Option<Move>)
+}
+
+struct Slot {
+    key: u64,
+    age: u8,
+    entry: TTEntry
+}
+
+pub struct TranspositionTable {
+    // a lock per slot, so the search threads rarely wait on each other
+    slots: Vec<Mutex<Option<Slot>>>,
+    age: AtomicU8
+}
+
+impl TranspositionTable {
+    pub fn new() -> Self {
+        Self {
+            slots: (0..TT_SIZE).map(|_| Mutex::new(None)).collect(),
+            age: AtomicU8::new(0)
+        }
+    }
+    pub fn key(state: &Board, color: Team) -> u64 {
+        // the zobrist keys only cover piece placement, so fold in the side to move
+        let side_key = if color == Team::White { 0x9e3779b97f4a7c15 } else { 0 };
+        state.zobrist_hash() ^ side_key
+    }
+    pub fn repetition_key(state: &Board, color: Team) -> u64 {
+        // a position only repeats when the same castling and en passant captures are available
+        let mut key = Self::key(state, color);
+        for (i, allowed) in state.castling_rights().iter().enumerate() {
+            if *allowed {
+                key ^= CASTLING_KEYS[i];
+            }
+        }
+        for tiles in state.tiles.iter() {
+            for (col, tile) in tiles.iter().enumerate() {
+                if !tile.has_team(color.other()) || (tile.piece().ptype != PieceType::Pawn) || !tile.piece().en_passant {
+                    continue;
+                }
+                if state.can_capture_en_passant(col, color) {
+                    key ^= EN_PASSANT_KEYS[col];
+                }
+            }
+        }
+        return key;
+    }
+    fn slot(&self, key: u64) -> &Mutex<Option<Slot>> {
+        &self.slots[key as usize & (TT_SIZE - 1)]
+    }
+    pub fn probe(&self, key: u64) -> Option<TTEntry> {
+        match &*self.slot(key).lock().unwrap() {
+            Some(slot) if slot.key == key => Some(slot.entry.clone()),
+            _ => None
+        }
+    }
+    pub fn store(&self, key: u64, entry: TTEntry) {
+        let age = self.age.load(Ordering::Relaxed);
+        let mut slot = self.slot(key).lock().unwrap();
+        // another position is only pushed out when it is left from an earlier search or searched less deeply
+        let replace = match &*slot {
+            Some(existing) if existing.key == key => existing.entry.depth <= entry.depth,
+            Some(existing) => (existing.age != age) || (existing.entry.depth <= entry.depth),
+            None => true
+        };
+        if replace {
+            *slot = Some(Slot {key: key, age: age, entry: entry});
+        }
+    }
+    pub fn new_search(&self) {
+        // entries of earlier searches stay usable until something replaces them
+        self.age.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn clear(&self) {
+        for slot in self.slots.iter() {
+            *slot.lock().unwrap() = None;
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(depth: isize) -> TTEntry {
+        TTEntry { depth: depth, eval: depth as f32, bound: Bound::Exact, best_move: (None, None) }
+    }
+
+    #[test]
+    fn keeps_deeper_entries_of_the_current_search() {
+        let tt = TranspositionTable::new();
+        let (key, other) = (7, 7 + TT_SIZE as u64);
+        tt.store(key, entry(4));
+        tt.store(other, entry(2));
+        assert_eq!(tt.probe(key).map(|entry| entry.depth), Some(4));
+        assert!(tt.probe(other).is_none());
+
+        tt.new_search();
+        tt.store(other, entry(2));
+        assert!(tt.probe(key).is_none());
+        assert_eq!(tt.probe(other).map(|entry| entry.depth), Some(2));
+    }
+}
\ No newline at end of file
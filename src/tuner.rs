@@ -0,0 +1,197 @@
+use std::fs;
+use ::rand::seq::SliceRandom;
+use ::rand::thread_rng;
+use rayon::prelude::*;
+use super::game::*;
+
+const PGN_DIR: &str = "assets/pgns";
+// book moves say little about the evaluation
+const SKIP_PLIES: usize = 16;
+const DEFAULT_POSITIONS: usize = 200000;
+const DEFAULT_ITERATIONS: usize = 300;
+const GAMES_PER_BATCH: usize = 256;
+// adam step size in evaluation units, and its usual decay rates
+const LEARNING_RATE: f64 = 2.0;
+const BETA1: f64 = 0.9;
+const BETA2: f64 = 0.999;
+
+
+struct Sample {
+    coefficients: Vec<(usize, f32)>,
+    // the game's result for white
+    result: f64
+}
+
+fn is_quiet(board: &mut Board, side: Team) -> bool {
+    // the static evaluation is only trusted when nothing is hanging
+    if board.get_pins_and_checks(side).0 {
+        return false;
+    }
+    board.calc_team_valid_moves(side);
+    for (piece, moves) in board.valid_moves.iter() {
+        if piece.color != side {
+            continue;
+        }
+        for action in moves.iter() {
+            let capture = board.tiles[action.end.row as usize][action.end.col as usize].has_piece();
+            if capture && (board.see(action) > 0) {
+                return false;
+            }
+        }
+    }
+    return true;
+}
+
+fn game_samples(pgn: &PgnGame) -> Option<Vec<Sample>> {
+    // none for games that cannot be replayed, unfinished games simply have nothing to learn from
+    let result = match pgn.result.as_str() {
+        "1-0" => 1.0,
+        "0-1" => 0.0,
+        "1/2-1/2" => 0.5,
+        _ => return Some(Vec::new())
+    };
+    let (mut board, mut side) = match pgn.tag("FEN") {
+        Some(fen) => Board::from_fen(fen).ok()?,
+        None => (Board::new(), Team::White)
+    };
+    let mut samples = Vec::new();
+    for (ply, pgn_move) in pgn.moves.iter().enumerate() {
+        if (ply >= SKIP_PLIES) && is_quiet(&mut board, side) {
+            samples.push(Sample {
+                coefficients: eval_coefficients(&board),
+                result: result
+            });
+        }
+        let (mut piece, action) = board.find_san(side, &pgn_move.san)?;
+        board.execute_move(&mut piece, action.copy(), false, false);
+        board.set_en_passant(&mut piece, action);
+        side = side.other();
+    }
+    return Some(samples);
+}
+
+fn load_samples(limit: usize) -> Vec<Sample> {
+    let mut paths: Vec<_> = match fs::read_dir(PGN_DIR) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(err) => {
+            eprintln!("could not read {}: {}", PGN_DIR, err);
+            return Vec::new();
+        }
+    };
+    paths.sort();
+    let mut games: Vec<PgnGame> = paths.iter().filter_map(|path| fs::read_to_string(path).ok()).flat_map(|text| parse_pgn(&text)).collect();
+    // positions from every era and event rather than the first files in the folder
+    games.shuffle(&mut thread_rng());
+    println!("{} games in {}", games.len(), PGN_DIR);
+
+    let mut samples = Vec::new();
+    let mut skipped = 0;
+    for batch in games.chunks(GAMES_PER_BATCH) {
+        if samples.len() >= limit {
+            break;
+        }
+        for game in batch.par_iter().map(game_samples).collect::<Vec<Option<Vec<Sample>>>>() {
+            match game {
+                Some(game) => samples.extend(game),
+                None => skipped += 1
+            }
+        }
+        println!("{} quiet positions, {} games skipped with illegal moves", samples.len().min(limit), skipped);
+    }
+    samples.truncate(limit);
+    return samples;
+}
+
+
+fn sigmoid(k: f64, evaluation: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-k * evaluation / 400.0))
+}
+
+fn predict(weights: &[f64], sample: &Sample) -> f64 {
+    sample.coefficients.iter().map(|(idx, value)| weights[*idx] * *value as f64).sum()
+}
+
+fn mean_error(samples: &[Sample], weights: &[f64], k: f64) -> f64 {
+    let total: f64 = samples.par_iter().map(|sample| (sample.result - sigmoid(k, predict(weights, sample))).powi(2)).sum();
+    return total / samples.len() as f64;
+}
+
+fn fit_k(samples: &[Sample], weights: &[f64]) -> f64 {
+    // the scale that best maps the current evaluation to results, found by ternary search
+    let (mut low, mut high) = (0.0, 5.0);
+    for _ in 0..40 {
+        let (a, b) = (low + (high - low) / 3.0, high - (high - low) / 3.0);
+        if mean_error(samples, weights, a) < mean_error(samples, weights, b) {
+            high = b;
+        }
+        else {
+            low = a;
+        }
+    }
+    return (low + high) / 2.0;
+}
+
+fn gradient(samples: &[Sample], weights: &[f64], k: f64) -> Vec<f64> {
+    let scale = k * 10f64.ln() / 400.0;
+    let mut grad = samples.par_iter().fold(|| vec![0.0; N_PARAMS], |mut grad, sample| {
+        let predicted = sigmoid(k, predict(weights, sample));
+        let factor = -2.0 * (sample.result - predicted) * predicted * (1.0 - predicted) * scale;
+        for (idx, value) in sample.coefficients.iter() {
+            grad[*idx] += factor * *value as f64;
+        }
+        grad
+    }).reduce(|| vec![0.0; N_PARAMS], |mut a, b| {
+        for (x, y) in a.iter_mut().zip(b.iter()) {
+            *x += y;
+        }
+        a
+    });
+    for g in grad.iter_mut() {
+        *g /= samples.len() as f64;
+    }
+    return grad;
+}
+
+
+pub fn run(args: &[String]) {
+    // chess --tune [--positions n] [--iterations n] [--out path]
+    let flag_value = |flag: &str| args.iter().position(|arg| arg == flag).and_then(|idx| args.get(idx + 1)).cloned();
+    let limit = flag_value("--positions").and_then(|n| n.parse().ok()).unwrap_or(DEFAULT_POSITIONS);
+    let iterations = flag_value("--iterations").and_then(|n| n.parse().ok()).unwrap_or(DEFAULT_ITERATIONS);
+    let out = flag_value("--out").unwrap_or(EVAL_FILE.to_string());
+
+    let samples = load_samples(limit);
+    if samples.is_empty() {
+        eprintln!("no positions to tune on");
+        return;
+    }
+    // tuning continues from whatever the engine currently uses
+    let mut weights = EvalParams::active().to_vector();
+    let k = fit_k(&samples, &weights);
+    println!("k = {:.4}, error {:.6}", k, mean_error(&samples, &weights, k));
+
+    let mut m = vec![0.0; N_PARAMS];
+    let mut v = vec![0.0; N_PARAMS];
+    for iteration in 1..=iterations {
+        let grad = gradient(&samples, &weights, k);
+        for i in 0..N_PARAMS {
+            m[i] = BETA1 * m[i] + (1.0 - BETA1) * grad[i];
+            v[i] = BETA2 * v[i] + (1.0 - BETA2) * grad[i] * grad[i];
+            let m_hat = m[i] / (1.0 - BETA1.powi(iteration as i32));
+            let v_hat = v[i] / (1.0 - BETA2.powi(iteration as i32));
+            weights[i] -= LEARNING_RATE * m_hat / (v_hat.sqrt() + 1e-12);
+        }
+        if (iteration % 10 == 0) || (iteration == iterations) {
+            println!("iteration {:>4}: error {:.6}", iteration, mean_error(&samples, &weights, k));
+        }
+    }
+
+    let params = EvalParams::from_vector(&weights);
+    println!("material mg {:?}", params.material_mg);
+    println!("material eg {:?}", params.material_eg);
+    println!("bishop pair {}", params.bishop_pair);
+    match params.save(&out) {
+        Ok(()) => println!("wrote {}, loaded the next time the engine starts", out),
+        Err(err) => eprintln!("{}", err)
+    }
+}
\ No newline at end of file
@@ -0,0 +1,182 @@
+use std::io::{self, BufRead, Write};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+use super::game::*;
+
+// how often a running search is checked for a result while waiting for input
+const POLL_MS: u64 = 10;
+
+
+pub fn run() {
+    let mut engine = Algorithms::new(Team::White);
+    let mut board = Board::new();
+    let mut next_player = Team::White;
+    let mut use_ponder = true;
+    let mut search: Option<BackgroundSearch> = None;
+    // a ponder search is only reported once the gui confirms the expected reply
+    let mut pondering = false;
+
+    // input is read on its own thread, so stop and quit reach a running search
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let line = match line {
+                Ok(val) => val,
+                Err(_) => break
+            };
+            if sender.send(line).is_err() {
+                break;
+            }
+        }
+    });
+    loop {
+        if !pondering && search.as_ref().map_or(false, |search| search.is_finished()) {
+            finish(&mut engine, search.take().unwrap().wait(), &board, use_ponder);
+            io::stdout().flush().unwrap();
+        }
+        let line = match receiver.recv_timeout(Duration::from_millis(POLL_MS)) {
+            Ok(val) => val,
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break
+        };
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        match tokens.first() {
+            Some(&"uci") => {
+                println!("id name Chess");
+                println!("option name Ponder type check default true");
+                println!("option name MultiPV type spin default 1 min 1 max {}", MAX_MULTI_PV);
+                println!("uciok");
+            },
+            Some(&"isready") => println!("readyok"),
+            Some(&"setoption") => {
+                if let Some(value) = option_value(&tokens[1..], "Ponder") {
+                    use_ponder = value == "true";
+                }
+                if let Some(value) = option_value(&tokens[1..], "MultiPV") {
+                    engine.multi_pv = value.parse().unwrap_or(1).clamp(1, MAX_MULTI_PV);
+                }
+            },
+            Some(&"ucinewgame") => {
+                engine.tt.clear();
+                board = Board::new();
+                next_player = Team::White;
+            },
+            Some(&"position") => {
+                (board, next_player) = set_position(&tokens[1..]);
+            },
+            Some(&"go") => {
+                let depth = parse_depth(&tokens[1..]);
+                if let Some(running) = search.take() {
+                    running.cancel();
+                }
+                engine.set_perspective(next_player);
+                // with ponder the position already includes the expected reply, it is searched until the gui tells us the outcome
+                pondering = tokens.contains(&"ponder");
+                search = Some(BackgroundSearch::start(&engine, board.copy(), depth));
+            },
+            Some(&"ponderhit") => {
+                // the search carries on as a normal one and reports when it is done
+                pondering = false;
+            },
+            Some(&"stop") => {
+                if let Some(running) = search.take() {
+                    pondering = false;
+                    finish(&mut engine, running.stop(), &board, use_ponder);
+                }
+            },
+            Some(&"quit") => {
+                if let Some(running) = search.take() {
+                    running.cancel();
+                }
+                break;
+            },
+            _ => ()
+        }
+        io::stdout().flush().unwrap();
+    }
+}
+
+fn option_value<'a>(tokens: &[&'a str], name: &str) -> Option<&'a str> {
+    let name_idx = tokens.iter().position(|t| *t == "name")?;
+    let value_idx = tokens.iter().position(|t| *t == "value")?;
+    if (name_idx < value_idx) && tokens[name_idx + 1..value_idx].join(" ").eq_ignore_ascii_case(name) {
+        return tokens.get(value_idx + 1).copied();
+    }
+    return None;
+}
+
+fn parse_depth(tokens: &[&str]) -> isize {
+    match tokens.iter().position(|t| *t == "depth") {
+        Some(idx) => tokens.get(idx + 1).and_then(|d| d.parse().ok()).unwrap_or(SEARCH_DEPTH),
+        None => SEARCH_DEPTH
+    }
+}
+
+fn set_position(tokens: &[&str]) -> (Board, Team) {
+    let moves_idx = tokens.iter().position(|t| *t == "moves");
+    let (mut board, mut next_player) = match tokens.first() {
+        Some(&"fen") => {
+            let fen = tokens[1..moves_idx.unwrap_or(tokens.len())].join(" ");
+            match Board::from_fen(&fen) {
+                Ok(position) => position,
+                Err(err) => {
+                    println!("info string invalid fen: {}", err);
+                    (Board::new(), Team::White)
+                }
+            }
+        },
+        _ => (Board::new(), Team::White)
+    };
+    if let Some(idx) = moves_idx {
+        for text in tokens[idx + 1..].iter() {
+            match board.find_move(next_player, text) {
+                Some((mut piece, action)) => {
+                    board.execute_move(&mut piece, action.copy(), false, false);
+                    board.set_en_passant(&mut piece, action);
+                    next_player = next_player.other();
+                },
+                None => {
+                    println!("info string illegal move {}", text);
+                    break;
+                }
+            }
+        }
+    }
+    return (board, next_player);
+}
+
+fn finish(engine: &mut Algorithms, outcome: SearchOutcome, board: &Board, use_ponder: bool) {
+    engine.stats = outcome.stats;
+    engine.lines = outcome.lines;
+    let mut result = outcome.result;
+    if result.1.0.is_none() {
+        // stopped before the first iteration finished, a shallow search still finds a move
+        result = engine.search_multi(board.copy(), 2);
+    }
+    report(engine, result, use_ponder);
+}
+
+fn report(engine: &Algorithms, result: SearchResult, use_ponder: bool) {
+    let (_evaluation, (piece, action)) = result;
+    if piece.is_none() || action.is_none() {
+        println!("bestmove 0000");
+        return;
+    }
+    let action = action.unwrap();
+    let mut best_pv = Vec::from([action.to_uci()]);
+    for (i, line) in engine.lines.iter().enumerate() {
+        let pv_text: Vec<String> = line.moves.iter().map(|(_, m)| m.to_uci()).collect();
+        println!("{}", engine.stats.uci_info(i + 1, engine.eval.to_centipawns(line.evaluation), &pv_text.join(" ")));
+        if i == 0 && !pv_text.is_empty() {
+            best_pv = pv_text;
+        }
+    }
+    println!("{}", engine.stats.uci_extra_info());
+    if use_ponder && (best_pv.len() > 1) {
+        println!("bestmove {} ponder {}", best_pv[0], best_pv[1]);
+    }
+    else {
+        println!("bestmove {}", best_pv[0]);
+    }
+}
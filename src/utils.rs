@@ -8,6 +8,34 @@ pub fn inrange(elem: isize) -> bool {
     return true;
 }
 
+pub fn square_name(row: isize, col: isize) -> String {
+    format!("{}{}", (b'a' + col as u8) as char, 8 - row)
+}
+
+pub fn parse_square(name: &str) -> Option<(isize, isize)> {
+    let bytes = name.as_bytes();
+    if (bytes.len() != 2) || !(b'a'..=b'h').contains(&bytes[0]) || !(b'1'..=b'8').contains(&bytes[1]) {
+        return None;
+    }
+    return Some((8 - (bytes[1] - b'0') as isize, (bytes[0] - b'a') as isize));
+}
+
+pub fn screen_to_tile(position: (f32, f32), tilesize: f32, flipped: bool) -> (isize, isize) {
+    let row = (position.1 / tilesize) as isize;
+    let col = (position.0 / tilesize) as isize;
+    if flipped {
+        return (7 - row, 7 - col);
+    }
+    return (row, col);
+}
+
+pub fn tile_to_screen(row: isize, col: isize, tilesize: f32, flipped: bool) -> (f32, f32) {
+    if flipped {
+        return ((7 - col) as f32 * tilesize, (7 - row) as f32 * tilesize);
+    }
+    return (col as f32 * tilesize, row as f32 * tilesize);
+}
+
 
 
 #[derive(Clone)]
@@ -17,7 +45,8 @@ pub struct Timer {
     pub start_time: SystemTime,
     pub percent_done: f32,
     pub active: bool,
-    pub finished: bool
+    pub finished: bool,
+    banked: u128
 }
 
 impl Timer {
@@ -28,7 +57,8 @@ impl Timer {
             start_time: SystemTime::now(),
             percent_done: 0.0,
             active: false,
-            finished: false
+            finished: false,
+            banked: 0
         }
     }
     pub fn reset(&mut self) {
@@ -36,24 +66,95 @@ impl Timer {
         self.finished = false;
         self.start_time = SystemTime::now();
         self.runtime = 0;
+        self.banked = 0;
+        self.percent_done = 0.0;
     }
     pub fn activate(&mut self) {
         self.active = true;
         self.finished = false;
         self.percent_done = 0.0;
         self.start_time = SystemTime::now();
+        self.runtime = 0;
+        self.banked = 0;
     }
     pub fn deactivate(&mut self) {
         self.active = false;
         self.start_time = SystemTime::now();
     }
+    pub fn pause(&mut self) {
+        if self.active {
+            self.update();
+            self.banked = self.runtime;
+            self.deactivate();
+        }
+    }
+    pub fn resume(&mut self) {
+        if !self.active && !self.finished {
+            self.active = true;
+            self.start_time = SystemTime::now();
+        }
+    }
+    pub fn remaining(&self) -> u128 {
+        self.duration.saturating_sub(self.runtime)
+    }
     pub fn update(&mut self) {
+        if !self.active {
+            return;
+        }
         let current_time = SystemTime::now();
-        self.runtime = current_time.duration_since(self.start_time).unwrap().as_millis();
+        self.runtime = self.banked + current_time.duration_since(self.start_time).unwrap().as_millis();
         self.percent_done = self.runtime as f32 / self.duration as f32;
         if self.runtime >= self.duration {
             self.finished = true;
             self.deactivate();
         }
     }
+}
+
+
+
+#[derive(Clone, Copy, PartialEq)]
+pub struct TimeControl {
+    pub base: u128,
+    pub increment: u128,
+    pub delay: u128
+}
+
+impl TimeControl {
+    pub fn new(minutes: u128, increment_secs: u128, delay_secs: u128) -> Self {
+        Self {
+            base: minutes * 60 * 1000,
+            increment: increment_secs * 1000,
+            delay: delay_secs * 1000
+        }
+    }
+    pub fn presets() -> Vec<Option<TimeControl>> {
+        Vec::from([
+            None,
+            Some(Self::new(1, 0, 0)),
+            Some(Self::new(3, 2, 0)),
+            Some(Self::new(5, 3, 0)),
+            Some(Self::new(10, 0, 0)),
+            Some(Self::new(15, 10, 0)),
+            Some(Self::new(30, 0, 0)),
+            Some(Self::new(90, 30, 0)),
+            Some(Self::new(90, 0, 5)),
+        ])
+    }
+    pub fn label(time_control: &Option<TimeControl>) -> String {
+        match time_control {
+            Some(tc) if tc.delay > 0 => format!("{} d{}", tc.base / 60000, tc.delay / 1000),
+            Some(tc) => format!("{}+{}", tc.base / 60000, tc.increment / 1000),
+            None => "None".to_string()
+        }
+    }
+}
+
+
+pub fn format_clock(millis: u128) -> String {
+    let secs = millis / 1000;
+    if secs < 10 {
+        return format!("0:{:02}.{}", secs, (millis % 1000) / 100);
+    }
+    format!("{}:{:02}", secs / 60, secs % 60)
 }
\ No newline at end of file